@@ -0,0 +1,391 @@
+use std::collections::HashSet;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use std::thread::spawn;
+use std::time::{Duration, Instant};
+
+use crossbeam::channel::{unbounded, Receiver, Sender};
+use strum::{EnumCount, IntoEnumIterator};
+
+use crate::events::{EventPublisher, InputEventKind};
+use crate::transport::HidTransport;
+use crate::virpil_device::{ToButtonIndex, VirpilDevice, VirpilDeviceDescription};
+
+/// A button on any device attached to an [`ActionMapper`], created through [`DeviceHandle::button`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct AnyButton {
+    /// Order the device was attached in.
+    pub device: usize,
+    pub button: u8,
+}
+
+/// Names the buttons of one attached device.
+pub struct DeviceHandle<D> {
+    device: usize,
+    _description: PhantomData<fn() -> D>,
+}
+impl<D> DeviceHandle<D>
+where
+    D: VirpilDeviceDescription,
+{
+    pub fn button(&self, button: D::Buttons) -> AnyButton {
+        AnyButton {
+            device: self.device,
+            button: button.to_button_index(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ChordInput {
+    Pressed(AnyButton),
+    Released(AnyButton),
+}
+
+/// Buttons that have to be in the given states at the same time, sent as an action once all of them are.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Chord {
+    pub name: &'static str,
+    pub inputs: Vec<ChordInput>,
+}
+
+/// Buttons pressed one after another, each within `max_gap` of the previous one.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Sequence {
+    pub name: &'static str,
+    pub steps: Vec<AnyButton>,
+    pub max_gap: Duration,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Action {
+    pub name: &'static str,
+    /// When the report completing the chord or sequence was read.
+    pub time: Instant,
+}
+
+struct ButtonChange {
+    button: AnyButton,
+    pressed: bool,
+    time: Instant,
+}
+
+#[derive(Default)]
+struct Matcher {
+    pressed: HashSet<AnyButton>,
+    /// Each chord and whether it is currently held.
+    chords: Vec<(Chord, bool)>,
+    /// Each sequence, the number of steps done and when the last one was.
+    sequences: Vec<(Sequence, usize, Option<Instant>)>,
+}
+impl Matcher {
+    fn update(&mut self, change: ButtonChange, out: &mut Vec<Action>) {
+        if change.pressed {
+            self.pressed.insert(change.button);
+        } else {
+            self.pressed.remove(&change.button);
+        }
+        for (chord, held) in self.chords.iter_mut() {
+            let matches = chord.inputs.iter().all(|input| match input {
+                ChordInput::Pressed(button) => self.pressed.contains(button),
+                ChordInput::Released(button) => !self.pressed.contains(button),
+            });
+            if matches && !*held {
+                out.push(Action {
+                    name: chord.name,
+                    time: change.time,
+                });
+            }
+            *held = matches;
+        }
+        if !change.pressed {
+            return;
+        }
+        for (sequence, done, last) in self.sequences.iter_mut() {
+            let in_time = last
+                .is_none_or(|last| change.time.saturating_duration_since(last) <= sequence.max_gap);
+            if !in_time {
+                *done = 0;
+            }
+            *done = steps_done(&sequence.steps, *done, change.button);
+            if *done == 0 {
+                continue;
+            }
+            *last = Some(change.time);
+            if *done == sequence.steps.len() {
+                *done = 0;
+                out.push(Action {
+                    name: sequence.name,
+                    time: change.time,
+                });
+            }
+        }
+    }
+}
+
+/// Steps done once `done` steps are followed by a press of `button`. On a mismatch this falls back to the longest
+/// start of `steps` the latest presses still match, so `a a a b` completes `a a b`.
+fn steps_done(steps: &[AnyButton], done: usize, button: AnyButton) -> usize {
+    let mut presses = steps[..done].to_vec();
+    presses.push(button);
+    (1..=presses.len().min(steps.len()))
+        .rev()
+        .find(|count| presses.ends_with(&steps[..*count]))
+        .unwrap_or(0)
+}
+
+/// Matches chords and sequences across several devices and sends named actions.
+///
+/// Every attached device gets a thread forwarding its button events, which ends once the device is dropped.
+pub struct ActionMapper {
+    changes: Sender<ButtonChange>,
+    matcher: Arc<Mutex<Matcher>>,
+    actions: Arc<EventPublisher<Action>>,
+    devices: usize,
+}
+impl Default for ActionMapper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl ActionMapper {
+    pub fn new() -> Self {
+        let (changes, receiver) = unbounded::<ButtonChange>();
+        let matcher = Arc::new(Mutex::new(Matcher::default()));
+        let actions = Arc::new(EventPublisher::default());
+        let thread_matcher = matcher.clone();
+        let thread_actions = actions.clone();
+        spawn(move || {
+            let mut out = Vec::new();
+            while let Ok(change) = receiver.recv() {
+                thread_matcher.lock().unwrap().update(change, &mut out);
+                thread_actions.publish(&out);
+                out.clear();
+            }
+        });
+        Self {
+            changes,
+            matcher,
+            actions,
+            devices: 0,
+        }
+    }
+
+    /// Starts following the buttons of `device`, including the ones already held.
+    pub fn attach<D, T>(&mut self, device: &VirpilDevice<D, T>) -> DeviceHandle<D>
+    where
+        D: VirpilDeviceDescription + 'static,
+        T: HidTransport,
+        [(); D::Axis::COUNT]:,
+        [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
+    {
+        let handle = DeviceHandle {
+            device: self.devices,
+            _description: PhantomData,
+        };
+        self.devices += 1;
+        let events = device.subscribe();
+        let snapshot = device.snapshot();
+        let changes = self.changes.clone();
+        let index = handle.device;
+        for button in D::Buttons::iter().filter(|button| snapshot.button(*button)) {
+            let _ = changes.send(ButtonChange {
+                button: handle.button(button),
+                pressed: true,
+                time: snapshot.time,
+            });
+        }
+        spawn(move || {
+            while let Ok(event) = events.recv() {
+                let (button, pressed) = match event.kind {
+                    InputEventKind::ButtonPressed(button) => (button, true),
+                    InputEventKind::ButtonReleased(button) => (button, false),
+                    _ => continue,
+                };
+                let change = ButtonChange {
+                    button: AnyButton {
+                        device: index,
+                        button: button.to_button_index(),
+                    },
+                    pressed,
+                    time: event.time,
+                };
+                if changes.send(change).is_err() {
+                    break;
+                }
+            }
+        });
+        handle
+    }
+
+    /// Adds `chord`, replacing any chord or sequence with the same name.
+    pub fn add_chord(&self, chord: Chord) {
+        let mut matcher = self.matcher.lock().unwrap();
+        Self::remove_from(&mut matcher, chord.name);
+        matcher.chords.push((chord, false));
+    }
+
+    /// Adds `sequence`, replacing any chord or sequence with the same name.
+    pub fn add_sequence(&self, sequence: Sequence) {
+        let mut matcher = self.matcher.lock().unwrap();
+        Self::remove_from(&mut matcher, sequence.name);
+        matcher.sequences.push((sequence, 0, None));
+    }
+
+    /// Returns whether a chord or sequence with that name existed.
+    pub fn remove(&self, name: &str) -> bool {
+        Self::remove_from(&mut self.matcher.lock().unwrap(), name)
+    }
+
+    /// Receives every matched action from now on.
+    pub fn subscribe(&self) -> Receiver<Action> {
+        self.actions.subscribe()
+    }
+
+    fn remove_from(matcher: &mut Matcher, name: &str) -> bool {
+        let count = matcher.chords.len() + matcher.sequences.len();
+        matcher.chords.retain(|(chord, _)| chord.name != name);
+        matcher
+            .sequences
+            .retain(|(sequence, _, _)| sequence.name != name);
+        count != matcher.chords.len() + matcher.sequences.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::left_panel::{LeftPanel, LeftPanelButtons};
+    use crate::simulator::SimulatedBackend;
+    use crate::throttle::{Throttle, ThrottleButtons};
+    use crate::virpil_device::find_device;
+    use crate::LedPower;
+
+    fn button(device: usize, button: u8) -> AnyButton {
+        AnyButton { device, button }
+    }
+
+    /// Feeds `(millis, button, pressed)` changes to `matcher`, returning the actions with their millisecond times.
+    fn run(matcher: &mut Matcher, changes: &[(u64, AnyButton, bool)]) -> Vec<(&'static str, u64)> {
+        let start = Instant::now();
+        let mut out = Vec::new();
+        for (millis, button, pressed) in changes {
+            matcher.update(
+                ButtonChange {
+                    button: *button,
+                    pressed: *pressed,
+                    time: start + Duration::from_millis(*millis),
+                },
+                &mut out,
+            );
+        }
+        out.iter()
+            .map(|action| (action.name, (action.time - start).as_millis() as u64))
+            .collect()
+    }
+
+    #[test]
+    fn chords_fire_once_per_hold() {
+        let (a, b, c) = (button(0, 1), button(1, 1), button(1, 2));
+        let mut matcher = Matcher::default();
+        matcher.chords.push((
+            Chord {
+                name: "chord",
+                inputs: vec![
+                    ChordInput::Pressed(a),
+                    ChordInput::Pressed(b),
+                    ChordInput::Released(c),
+                ],
+            },
+            false,
+        ));
+        let actions = run(
+            &mut matcher,
+            &[
+                (0, a, true),
+                (10, b, true),
+                // Pressing c breaks the chord, releasing it completes it again.
+                (20, c, true),
+                (30, c, false),
+                (40, a, false),
+                (50, a, true),
+            ],
+        );
+        assert_eq!(actions, vec![("chord", 10), ("chord", 30), ("chord", 50)]);
+    }
+
+    #[test]
+    fn sequences_need_every_step_in_time() {
+        let (a, b, c) = (button(0, 1), button(0, 2), button(1, 1));
+        let mut matcher = Matcher::default();
+        for (name, steps) in [("sequence", vec![a, b, c]), ("repeated", vec![a, a, b])] {
+            matcher.sequences.push((
+                Sequence {
+                    name,
+                    steps,
+                    max_gap: Duration::from_millis(300),
+                },
+                0,
+                None,
+            ));
+        }
+        let press = |millis, button| [(millis, button, true), (millis + 50, button, false)];
+        let changes: Vec<_> = [
+            press(0, a),
+            press(100, b),
+            press(200, c),
+            // Too slow.
+            press(1000, a),
+            press(1500, b),
+            press(1600, c),
+            // A wrong step restarts, a repeated first step starts over from it.
+            press(2000, a),
+            press(2100, c),
+            press(2200, a),
+            press(2300, a),
+            press(2400, b),
+            press(2500, c),
+            // A repeated step keeps the presses before it that still fit.
+            press(3000, a),
+            press(3100, a),
+            press(3200, a),
+            press(3300, b),
+        ]
+        .concat();
+        assert_eq!(
+            run(&mut matcher, &changes),
+            vec![
+                ("sequence", 200),
+                ("repeated", 2400),
+                ("sequence", 2500),
+                ("repeated", 3300),
+            ]
+        );
+    }
+
+    #[test]
+    fn mapper_matches_across_devices() {
+        let backend = SimulatedBackend::new();
+        let mut throttle = backend.add_device::<Throttle>();
+        let mut panel = backend.add_device::<LeftPanel>();
+        let throttle_device = find_device::<Throttle, _>(&backend, LedPower::OFF).unwrap();
+        let panel_device = find_device::<LeftPanel, _>(&backend, LedPower::OFF).unwrap();
+        let mut mapper = ActionMapper::new();
+        let throttle_handle = mapper.attach(&throttle_device);
+        let panel_handle = mapper.attach(&panel_device);
+        mapper.add_chord(Chord {
+            name: "both",
+            inputs: vec![
+                ChordInput::Pressed(throttle_handle.button(ThrottleButtons::B1)),
+                ChordInput::Pressed(panel_handle.button(LeftPanelButtons::B1)),
+            ],
+        });
+        let actions = mapper.subscribe();
+        throttle.press(ThrottleButtons::B1).send_report();
+        panel.press(LeftPanelButtons::B1).send_report();
+        let action = actions.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(action.name, "both");
+        assert!(mapper.remove("both"));
+        assert!(!mapper.remove("both"));
+    }
+}
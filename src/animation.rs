@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use strum::IntoEnumIterator;
+
+use crate::virpil_device::VirpilDeviceDescription;
+use crate::{Color, LedPower};
+
+/// How often running effects are advanced.
+pub const ANIMATION_TICK: Duration = Duration::from_millis(20);
+
+/// The four [`LedPower`] levels up and back down, one period of [`Effect::Breathe`].
+const BREATHE_LEVELS: [u8; 6] = [0, 1, 2, 3, 2, 1];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Effect {
+    /// Alternates between `color` and off, `hz` times per second.
+    Blink { color: Color, hz: f32 },
+    /// Shows `color` for `on` at the start of every `period`, off otherwise.
+    Pulse {
+        color: Color,
+        period: Duration,
+        on: Duration,
+    },
+    /// Fades `color` up through the four [`LedPower`] levels and back down once per `period`.
+    Breathe { color: Color, period: Duration },
+    /// Steps through [`LedPower::COLOR_PROGRESSION`], showing each color for `step`.
+    ColorCycle { step: Duration },
+    /// Shows `color` for `duration` once, then stops.
+    Flash { color: Color, duration: Duration },
+}
+impl Effect {
+    /// The color `elapsed` into the effect, `None` once a one-shot effect is over.
+    pub fn color_at(&self, elapsed: Duration) -> Option<Color> {
+        match *self {
+            Effect::Blink { color, hz } => {
+                let half_periods = (elapsed.as_secs_f32() * hz.max(0.0) * 2.0) as u64;
+                Some(if half_periods & 1 == 0 {
+                    color
+                } else {
+                    LedPower::OFF
+                })
+            }
+            Effect::Pulse { color, period, on } => Some(if phase(elapsed, period) < on {
+                color
+            } else {
+                LedPower::OFF
+            }),
+            Effect::Breathe { color, period } => {
+                let step = step_index(phase(elapsed, period), period, BREATHE_LEVELS.len());
+                let level = BREATHE_LEVELS[step];
+                Some(color.map(|channel| LedPower::from_level((channel as u8).min(level))))
+            }
+            Effect::ColorCycle { step } => {
+                let progression = LedPower::COLOR_PROGRESSION;
+                let cycle = step * progression.len() as u32;
+                Some(progression[step_index(phase(elapsed, cycle), cycle, progression.len())])
+            }
+            Effect::Flash { color, duration } => (elapsed < duration).then_some(color),
+        }
+    }
+}
+
+fn phase(elapsed: Duration, period: Duration) -> Duration {
+    if period.is_zero() {
+        return Duration::ZERO;
+    }
+    Duration::from_nanos((elapsed.as_nanos() % period.as_nanos()) as u64)
+}
+
+fn step_index(phase: Duration, period: Duration, steps: usize) -> usize {
+    if period.is_zero() {
+        return 0;
+    }
+    ((phase.as_nanos() * steps as u128 / period.as_nanos()) as usize).min(steps - 1)
+}
+
+/// Effects running on the LEDs of a device, driven by the animation thread.
+pub struct Animations<D>
+where
+    D: VirpilDeviceDescription,
+{
+    effects: HashMap<D::Led, (Effect, Instant)>,
+    /// What was last sent to the writer for each animated LED.
+    shown: HashMap<D::Led, Color>,
+}
+impl<D> Default for Animations<D>
+where
+    D: VirpilDeviceDescription,
+{
+    fn default() -> Self {
+        Self {
+            effects: HashMap::new(),
+            shown: HashMap::new(),
+        }
+    }
+}
+impl<D> Animations<D>
+where
+    D: VirpilDeviceDescription,
+{
+    /// Starts `effect` on `led` from its beginning, replacing the running one.
+    pub fn start(&mut self, led: D::Led, effect: Effect, now: Instant) -> Option<Effect> {
+        self.effects
+            .insert(led, (effect, now))
+            .map(|(effect, _)| effect)
+    }
+
+    pub fn stop(&mut self, led: D::Led) -> Option<Effect> {
+        self.effects.remove(&led).map(|(effect, _)| effect)
+    }
+
+    pub fn effect(&self, led: D::Led) -> Option<Effect> {
+        self.effects.get(&led).map(|(effect, _)| *effect)
+    }
+
+    /// Advances every effect to `now`, returning the LEDs whose shown color changed. `None` hands the LED back to
+    /// its regular color.
+    pub fn tick(&mut self, now: Instant) -> Vec<(D::Led, Option<Color>)> {
+        let mut changes = Vec::new();
+        for led in D::Led::iter() {
+            let color = self
+                .effects
+                .get(&led)
+                .and_then(|(effect, start)| effect.color_at(now.saturating_duration_since(*start)));
+            if color.is_none() {
+                self.effects.remove(&led);
+            }
+            let changed = match color {
+                Some(color) => self.shown.insert(led, color) != Some(color),
+                None => self.shown.remove(&led).is_some(),
+            };
+            if changed {
+                changes.push((led, color));
+            }
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::left_panel::{LeftPanel, LeftPanelLed};
+
+    fn colors_at(effect: Effect, millis: &[u64]) -> Vec<Option<Color>> {
+        millis
+            .iter()
+            .map(|millis| effect.color_at(Duration::from_millis(*millis)))
+            .collect()
+    }
+
+    #[test]
+    fn effects_follow_their_timing() {
+        let (red, off) = (Some(LedPower::FULL_RED), Some(LedPower::OFF));
+        let blink = Effect::Blink {
+            color: LedPower::FULL_RED,
+            hz: 2.0,
+        };
+        assert_eq!(
+            colors_at(blink, &[0, 249, 250, 499, 500]),
+            vec![red, red, off, off, red]
+        );
+        let pulse = Effect::Pulse {
+            color: LedPower::FULL_RED,
+            period: Duration::from_millis(1000),
+            on: Duration::from_millis(100),
+        };
+        assert_eq!(
+            colors_at(pulse, &[0, 99, 100, 999, 1000]),
+            vec![red, red, off, off, red]
+        );
+        let flash = Effect::Flash {
+            color: LedPower::FULL_RED,
+            duration: Duration::from_millis(100),
+        };
+        assert_eq!(colors_at(flash, &[0, 99, 100]), vec![red, red, None]);
+    }
+
+    #[test]
+    fn breathe_caps_every_channel_at_the_level() {
+        let breathe = Effect::Breathe {
+            color: [LedPower::Full, LedPower::Thirty, LedPower::Zero],
+            period: Duration::from_millis(600),
+        };
+        let levels: Vec<_> = colors_at(breathe, &[0, 100, 200, 300, 400, 500, 600])
+            .into_iter()
+            .map(|color| color.unwrap())
+            .collect();
+        use LedPower::{Full, Sixty, Thirty, Zero};
+        assert_eq!(
+            levels,
+            vec![
+                [Zero, Zero, Zero],
+                [Thirty, Thirty, Zero],
+                [Sixty, Thirty, Zero],
+                [Full, Thirty, Zero],
+                [Sixty, Thirty, Zero],
+                [Thirty, Thirty, Zero],
+                [Zero, Zero, Zero],
+            ]
+        );
+    }
+
+    #[test]
+    fn color_cycle_steps_through_the_progression() {
+        let cycle = Effect::ColorCycle {
+            step: Duration::from_millis(100),
+        };
+        let progression = LedPower::COLOR_PROGRESSION;
+        for (index, color) in progression.iter().enumerate() {
+            assert_eq!(
+                cycle.color_at(Duration::from_millis(100 * index as u64 + 50)),
+                Some(*color)
+            );
+        }
+        assert_eq!(
+            cycle.color_at(Duration::from_millis(100 * progression.len() as u64)),
+            Some(progression[0])
+        );
+        assert_eq!(
+            Effect::ColorCycle {
+                step: Duration::ZERO
+            }
+            .color_at(Duration::from_secs(1)),
+            Some(progression[0])
+        );
+    }
+
+    #[test]
+    fn tick_reports_only_changes() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut animations = Animations::<LeftPanel>::default();
+        animations.start(
+            LeftPanelLed::B1,
+            Effect::Flash {
+                color: LedPower::FULL_RED,
+                duration: Duration::from_millis(100),
+            },
+            start,
+        );
+        assert_eq!(
+            animations.tick(at(0)),
+            vec![(LeftPanelLed::B1, Some(LedPower::FULL_RED))]
+        );
+        assert_eq!(animations.tick(at(50)), vec![]);
+        assert_eq!(animations.tick(at(100)), vec![(LeftPanelLed::B1, None)]);
+        // A finished one-shot effect is removed.
+        assert_eq!(animations.effect(LeftPanelLed::B1), None);
+        assert_eq!(animations.tick(at(150)), vec![]);
+        animations.start(
+            LeftPanelLed::B2,
+            Effect::Blink {
+                color: LedPower::FULL_RED,
+                hz: 1.0,
+            },
+            at(200),
+        );
+        animations.tick(at(200));
+        assert!(animations.stop(LeftPanelLed::B2).is_some());
+        assert_eq!(animations.tick(at(210)), vec![(LeftPanelLed::B2, None)]);
+    }
+}
@@ -0,0 +1,574 @@
+use std::marker::PhantomData;
+use std::mem::replace;
+
+use strum::{EnumCount, IntoEnumIterator};
+
+use crate::virpil_device::{ToAxisIndex, ToAxisInfo, VirpilDeviceDescription, MAX_AXIS_VALUE};
+
+/// Bits per axis value the devices report, the Configurator's `Precision` column.
+pub const DEFAULT_AXIS_RESOLUTION: u8 = 14;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum AxisKind {
+    /// Rests in the middle of its range, sticks and mini sticks.
+    Centered,
+    /// Rests at one end of its range, throttles, flaps, sliders and rotaries.
+    Unipolar,
+}
+
+/// What an axis physically is, declared per axis by each device description.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct AxisInfo {
+    pub kind: AxisKind,
+    /// Number of significant bits in the raw value.
+    pub resolution: u8,
+}
+impl AxisInfo {
+    pub const CENTERED: Self = Self {
+        kind: AxisKind::Centered,
+        resolution: DEFAULT_AXIS_RESOLUTION,
+    };
+    pub const UNIPOLAR: Self = Self {
+        kind: AxisKind::Unipolar,
+        resolution: DEFAULT_AXIS_RESOLUTION,
+    };
+
+    /// `2^resolution`, saturating at [`u16::MAX`] for resolutions of 16 bits and more.
+    pub fn max_value(&self) -> u16 {
+        1u32.checked_shl(self.resolution as u32)
+            .map_or(u16::MAX, |max| max.min(u16::MAX as u32) as u16)
+    }
+
+    /// Uncalibrated config spanning the full resolution, centered axes get their center at half the range.
+    pub fn default_config(&self) -> AxisConfig {
+        let max = self.max_value();
+        AxisConfig {
+            calibration: match self.kind {
+                AxisKind::Centered => Calibration::WithCenter {
+                    min: 0,
+                    mid: max / 2,
+                    max,
+                },
+                AxisKind::Unipolar => Calibration::NoCenter { min: 0, max },
+            },
+            ..AxisConfig::default()
+        }
+    }
+
+    /// Maps a processed `0..=1` value to `-1..=1` for centered axes, unipolar values are passed through.
+    pub fn normalize(&self, processed: f32) -> f32 {
+        match self.kind {
+            AxisKind::Centered => processed * 2.0 - 1.0,
+            AxisKind::Unipolar => processed,
+        }
+    }
+}
+
+/// Per axis processing, mirroring the Configurator's `AXES_TABLE` columns.
+///
+/// Steps are applied as offset, calibration, inversion, deadzones, curve and finally the dynamic deadzone. Deadzones
+/// are fractions of the full output range, `0.02` being the Configurator's `2`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AxisConfig {
+    pub inversion: bool,
+    /// Added to the raw value before calibration.
+    pub offset: i32,
+    pub calibration: Calibration,
+    /// `DZ Min`, pulls values near the low end to exactly `0`.
+    pub deadzone_min: f32,
+    /// `DZ Mid`, pulls values near the center to exactly `0.5`, only used with [`Calibration::WithCenter`].
+    pub deadzone_mid: f32,
+    /// `DZ Max`, pulls values near the high end to exactly `1`.
+    pub deadzone_max: f32,
+    /// `Dyn.DZ`, changes smaller than this are ignored so a resting axis does not jitter.
+    pub dynamic_deadzone: f32,
+    /// `C.01` to `C.10`, see [`AxisCurve`].
+    pub curve: Option<AxisCurve>,
+}
+impl Default for AxisConfig {
+    fn default() -> Self {
+        Self {
+            inversion: false,
+            offset: 0,
+            calibration: Calibration::None,
+            deadzone_min: 0.0,
+            deadzone_mid: 0.0,
+            deadzone_max: 0.0,
+            dynamic_deadzone: 0.0,
+            curve: None,
+        }
+    }
+}
+impl AxisConfig {
+    /// Runs every step except the dynamic deadzone, which needs the previous output.
+    pub fn apply(&self, raw: u16) -> f32 {
+        let value = (raw as i32 + self.offset).clamp(0, u16::MAX as i32) as f32;
+        let mut value = self.calibration.normalize(value);
+        if self.inversion {
+            value = 1.0 - value;
+        }
+        match self.calibration {
+            Calibration::WithCenter { .. } => {
+                // Work on the distance from the center, which spans half the range.
+                let value = if value < 0.5 {
+                    0.5 - 0.5
+                        * deadzone(
+                            1.0 - 2.0 * value,
+                            2.0 * self.deadzone_mid,
+                            2.0 * self.deadzone_min,
+                        )
+                } else {
+                    0.5 + 0.5
+                        * deadzone(
+                            2.0 * value - 1.0,
+                            2.0 * self.deadzone_mid,
+                            2.0 * self.deadzone_max,
+                        )
+                };
+                match &self.curve {
+                    Some(curve) if value < 0.5 => 0.5 - 0.5 * curve.apply(1.0 - 2.0 * value),
+                    Some(curve) => 0.5 + 0.5 * curve.apply(2.0 * value - 1.0),
+                    None => value,
+                }
+            }
+            _ => {
+                let value = deadzone(value, self.deadzone_min, self.deadzone_max);
+                match &self.curve {
+                    Some(curve) => curve.apply(value),
+                    None => value,
+                }
+            }
+        }
+    }
+
+    /// Applies the dynamic deadzone given the last output, always letting the ends and center through.
+    pub fn apply_dynamic_deadzone(&self, last: f32, value: f32) -> f32 {
+        let resting = value == 0.0
+            || value == 1.0
+            || (value == 0.5 && matches!(self.calibration, Calibration::WithCenter { .. }));
+        if (value - last).abs() < self.dynamic_deadzone && !resting {
+            last
+        } else {
+            value
+        }
+    }
+}
+
+/// Raw value calibration, the Configurator's `Calibration`, `Cal.Min`, `Cal.Mid` and `Cal.Max` columns.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Calibration {
+    /// Raw value over [`MAX_AXIS_VALUE`].
+    None,
+    /// `No center`, linear from `min` to `max`.
+    NoCenter { min: u16, max: u16 },
+    /// `With center`, `min..mid` and `mid..max` are scaled separately so `mid` maps to exactly `0.5`.
+    WithCenter { min: u16, mid: u16, max: u16 },
+}
+impl Calibration {
+    /// Maps a raw value to `0..=1`.
+    pub fn normalize(&self, value: f32) -> f32 {
+        let out = match *self {
+            Calibration::None => value / MAX_AXIS_VALUE as f32,
+            Calibration::NoCenter { min, max } => scale(value, min, max),
+            Calibration::WithCenter { min, mid, max } => {
+                if value < mid as f32 {
+                    0.5 * scale(value, min, mid)
+                } else {
+                    0.5 + 0.5 * scale(value, mid, max)
+                }
+            }
+        };
+        out.clamp(0.0, 1.0)
+    }
+}
+
+/// Ten point response curve, point `n` being the output at input `n / 10` with `0` mapping to `0`.
+///
+/// Centered axes apply the curve to the distance from the center.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AxisCurve {
+    pub points: [f32; 10],
+}
+impl AxisCurve {
+    pub const LINEAR: Self = Self {
+        points: [0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9, 1.0],
+    };
+
+    /// Linearly interpolates `value` in `0..=1` between the points.
+    pub fn apply(&self, value: f32) -> f32 {
+        let position = value.clamp(0.0, 1.0) * 10.0;
+        let index = (position as usize).min(9);
+        let low = if index == 0 {
+            0.0
+        } else {
+            self.points[index - 1]
+        };
+        let high = self.points[index];
+        let fraction = position - index as f32;
+        (low + (high - low) * fraction).clamp(0.0, 1.0)
+    }
+}
+
+/// Per axis configuration and last outputs for a whole device, driven by the reader thread.
+pub struct AxisPipeline<D>
+where
+    D: VirpilDeviceDescription,
+{
+    configs: Vec<AxisConfig>,
+    outputs: Vec<f32>,
+    _description: PhantomData<fn() -> D>,
+}
+impl<D> Default for AxisPipeline<D>
+where
+    D: VirpilDeviceDescription,
+{
+    fn default() -> Self {
+        let mut configs = vec![AxisConfig::default(); D::Axis::COUNT];
+        for axis in D::Axis::iter() {
+            configs[axis.to_axis_index() as usize] = axis.to_axis_info().default_config();
+        }
+        Self {
+            configs,
+            outputs: vec![0.0; D::Axis::COUNT],
+            _description: PhantomData,
+        }
+    }
+}
+impl<D> AxisPipeline<D>
+where
+    D: VirpilDeviceDescription,
+{
+    pub fn config(&self, axis: D::Axis) -> AxisConfig {
+        self.configs[axis.to_axis_index() as usize]
+    }
+
+    pub fn set_config(&mut self, axis: D::Axis, config: AxisConfig) -> AxisConfig {
+        replace(&mut self.configs[axis.to_axis_index() as usize], config)
+    }
+
+    pub fn output(&self, axis: D::Axis) -> f32 {
+        self.outputs[axis.to_axis_index() as usize]
+    }
+
+    /// Processes one report's raw axis values, in axis index order.
+    pub fn update(&mut self, raw: &[u16]) {
+        for ((config, output), raw) in self.configs.iter().zip(&mut self.outputs).zip(raw) {
+            *output = config.apply_dynamic_deadzone(*output, config.apply(*raw));
+        }
+    }
+}
+
+fn scale(value: f32, low: u16, high: u16) -> f32 {
+    if high <= low {
+        return if value >= high as f32 { 1.0 } else { 0.0 };
+    }
+    (value - low as f32) / (high - low) as f32
+}
+
+/// Maps `low..=1 - high` onto `0..=1`.
+fn deadzone(value: f32, low: f32, high: f32) -> f32 {
+    let range = 1.0 - low - high;
+    if range <= 0.0 {
+        return if value >= 0.5 { 1.0 } else { 0.0 };
+    }
+    ((value - low) / range).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::throttle::{Throttle, ThrottleAxis};
+
+    const SQUARED: AxisCurve = AxisCurve {
+        points: [0.01, 0.04, 0.09, 0.16, 0.25, 0.36, 0.49, 0.64, 0.81, 1.0],
+    };
+
+    fn assert_table(config: AxisConfig, table: &[(u16, f32)]) {
+        for (raw, expected) in table {
+            let value = config.apply(*raw);
+            assert!(
+                (value - expected).abs() < 1e-5,
+                "{:?} at {}: {} != {}",
+                config,
+                raw,
+                value,
+                expected
+            );
+        }
+    }
+
+    fn no_center(min: u16, max: u16) -> AxisConfig {
+        AxisConfig {
+            calibration: Calibration::NoCenter { min, max },
+            ..AxisConfig::default()
+        }
+    }
+
+    fn with_center(min: u16, mid: u16, max: u16) -> AxisConfig {
+        AxisConfig {
+            calibration: Calibration::WithCenter { min, mid, max },
+            ..AxisConfig::default()
+        }
+    }
+
+    #[test]
+    fn uncalibrated_spans_max_axis_value() {
+        assert_table(
+            AxisConfig::default(),
+            &[
+                (0, 0.0),
+                (MAX_AXIS_VALUE / 4, 0.25),
+                (MAX_AXIS_VALUE, 1.0),
+                (u16::MAX, 1.0),
+            ],
+        );
+    }
+
+    #[test]
+    fn no_center_calibration() {
+        assert_table(
+            no_center(1000, 3000),
+            &[(0, 0.0), (1000, 0.0), (2000, 0.5), (3000, 1.0), (4000, 1.0)],
+        );
+        assert_table(no_center(100, 100), &[(99, 0.0), (100, 1.0)]);
+    }
+
+    #[test]
+    fn with_center_calibration() {
+        assert_table(
+            with_center(1000, 2000, 5000),
+            &[
+                (0, 0.0),
+                (1000, 0.0),
+                (1500, 0.25),
+                (2000, 0.5),
+                (3500, 0.75),
+                (5000, 1.0),
+                (6000, 1.0),
+            ],
+        );
+    }
+
+    #[test]
+    fn offset_shifts_raw_values() {
+        let config = AxisConfig {
+            offset: 100,
+            ..no_center(0, 1000)
+        };
+        assert_table(config, &[(0, 0.1), (400, 0.5), (900, 1.0)]);
+        let config = AxisConfig {
+            offset: -100,
+            ..no_center(0, 1000)
+        };
+        assert_table(config, &[(50, 0.0), (600, 0.5)]);
+    }
+
+    #[test]
+    fn inversion_mirrors_the_range() {
+        let config = AxisConfig {
+            inversion: true,
+            ..no_center(0, 1000)
+        };
+        assert_table(config, &[(0, 1.0), (250, 0.75), (1000, 0.0)]);
+        let config = AxisConfig {
+            inversion: true,
+            ..with_center(0, 400, 1000)
+        };
+        assert_table(config, &[(0, 1.0), (400, 0.5), (700, 0.25), (1000, 0.0)]);
+    }
+
+    #[test]
+    fn end_deadzones() {
+        let config = AxisConfig {
+            deadzone_min: 0.1,
+            deadzone_max: 0.1,
+            ..no_center(0, 1000)
+        };
+        assert_table(
+            config,
+            &[
+                (50, 0.0),
+                (100, 0.0),
+                (300, 0.25),
+                (500, 0.5),
+                (900, 1.0),
+                (950, 1.0),
+            ],
+        );
+        let config = AxisConfig {
+            deadzone_min: 0.05,
+            deadzone_max: 0.05,
+            ..with_center(0, 500, 1000)
+        };
+        assert_table(
+            config,
+            &[(0, 0.0), (25, 0.0), (500, 0.5), (975, 1.0), (1000, 1.0)],
+        );
+    }
+
+    #[test]
+    fn mid_deadzone() {
+        let config = AxisConfig {
+            deadzone_mid: 0.1,
+            ..with_center(0, 500, 1000)
+        };
+        assert_table(
+            config,
+            &[
+                (0, 0.0),
+                (250, 0.3125),
+                (450, 0.5),
+                (500, 0.5),
+                (550, 0.5),
+                (750, 0.6875),
+                (1000, 1.0),
+            ],
+        );
+        // Without a center there is nothing to pull towards.
+        let config = AxisConfig {
+            deadzone_mid: 0.1,
+            ..no_center(0, 1000)
+        };
+        assert_table(config, &[(500, 0.5), (550, 0.55)]);
+    }
+
+    #[test]
+    fn curve_interpolates_between_points() {
+        for (value, expected) in [
+            (0.0, 0.0),
+            (0.05, 0.005),
+            (0.1, 0.01),
+            (0.35, 0.125),
+            (0.95, 0.905),
+            (1.0, 1.0),
+            (1.5, 1.0),
+        ] {
+            let out = SQUARED.apply(value);
+            assert!(
+                (out - expected).abs() < 1e-5,
+                "{}: {} != {}",
+                value,
+                out,
+                expected
+            );
+        }
+        assert!((AxisCurve::LINEAR.apply(0.35) - 0.35).abs() < 1e-5);
+    }
+
+    #[test]
+    fn curve_on_centered_axes_uses_the_distance_from_center() {
+        let config = AxisConfig {
+            curve: Some(SQUARED),
+            ..with_center(0, 500, 1000)
+        };
+        assert_table(
+            config,
+            &[
+                (0, 0.0),
+                (250, 0.375),
+                (500, 0.5),
+                (750, 0.625),
+                (1000, 1.0),
+            ],
+        );
+        let config = AxisConfig {
+            curve: Some(SQUARED),
+            ..no_center(0, 1000)
+        };
+        assert_table(config, &[(0, 0.0), (500, 0.25), (1000, 1.0)]);
+    }
+
+    #[test]
+    fn dynamic_deadzone_holds_small_changes() {
+        let config = AxisConfig {
+            dynamic_deadzone: 0.05,
+            ..no_center(0, 1000)
+        };
+        assert_eq!(config.apply_dynamic_deadzone(0.5, 0.52), 0.5);
+        assert_eq!(config.apply_dynamic_deadzone(0.5, 0.6), 0.6);
+        assert_eq!(config.apply_dynamic_deadzone(0.02, 0.0), 0.0);
+        assert_eq!(config.apply_dynamic_deadzone(0.98, 1.0), 1.0);
+        assert_eq!(config.apply_dynamic_deadzone(0.52, 0.5), 0.52);
+        let config = AxisConfig {
+            dynamic_deadzone: 0.05,
+            ..with_center(0, 500, 1000)
+        };
+        assert_eq!(config.apply_dynamic_deadzone(0.52, 0.5), 0.5);
+    }
+
+    #[test]
+    fn pipeline_runs_every_axis() {
+        let mut pipeline = AxisPipeline::<Throttle>::default();
+        pipeline.set_config(
+            ThrottleAxis::Slider,
+            AxisConfig {
+                dynamic_deadzone: 0.1,
+                ..no_center(0, 1000)
+            },
+        );
+        let mut raw = [0; ThrottleAxis::COUNT];
+        raw[ThrottleAxis::StickX.to_axis_index() as usize] = MAX_AXIS_VALUE / 2;
+        raw[ThrottleAxis::Flaps.to_axis_index() as usize] = MAX_AXIS_VALUE;
+        raw[ThrottleAxis::Slider.to_axis_index() as usize] = 500;
+        pipeline.update(&raw);
+        assert_eq!(pipeline.output(ThrottleAxis::StickX), 0.5);
+        assert_eq!(pipeline.output(ThrottleAxis::Flaps), 1.0);
+        assert_eq!(pipeline.output(ThrottleAxis::Slider), 0.5);
+        raw[ThrottleAxis::Slider.to_axis_index() as usize] = 550;
+        pipeline.update(&raw);
+        assert_eq!(pipeline.output(ThrottleAxis::Slider), 0.5);
+    }
+
+    #[test]
+    fn max_value_saturates() {
+        for (resolution, max) in [
+            (DEFAULT_AXIS_RESOLUTION, MAX_AXIS_VALUE),
+            (8, 256),
+            (16, u16::MAX),
+            (32, u16::MAX),
+            (u8::MAX, u16::MAX),
+        ] {
+            let info = AxisInfo {
+                kind: AxisKind::Unipolar,
+                resolution,
+            };
+            assert_eq!(info.max_value(), max, "{}", resolution);
+        }
+    }
+
+    #[test]
+    fn default_configs_match_the_axis_kind() {
+        assert_eq!(
+            AxisInfo::CENTERED.default_config().calibration,
+            Calibration::WithCenter {
+                min: 0,
+                mid: MAX_AXIS_VALUE / 2,
+                max: MAX_AXIS_VALUE,
+            }
+        );
+        assert_eq!(
+            AxisInfo::UNIPOLAR.default_config().calibration,
+            Calibration::NoCenter {
+                min: 0,
+                max: MAX_AXIS_VALUE,
+            }
+        );
+        assert_table(
+            AxisInfo::CENTERED.default_config(),
+            &[(0, 0.0), (MAX_AXIS_VALUE / 2, 0.5), (MAX_AXIS_VALUE, 1.0)],
+        );
+    }
+
+    #[test]
+    fn centered_axes_normalize_around_zero() {
+        for (processed, centered, unipolar) in [
+            (0.0, -1.0, 0.0),
+            (0.25, -0.5, 0.25),
+            (0.5, 0.0, 0.5),
+            (1.0, 1.0, 1.0),
+        ] {
+            assert_eq!(AxisInfo::CENTERED.normalize(processed), centered);
+            assert_eq!(AxisInfo::UNIPOLAR.normalize(processed), unipolar);
+        }
+    }
+}
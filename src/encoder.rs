@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use strum::{EnumCount, IntoEnumIterator};
+
+use crate::snapshot::Snapshot;
+use crate::virpil_device::{ToEncoderButtons, VirpilDeviceDescription};
+
+/// Speeds an encoder up while it is turned quickly.
+///
+/// Every detent arriving within `interval` of the previous one in the same direction counts one step more than the
+/// previous detent did, up to `max_steps` per detent.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct EncoderAcceleration {
+    pub interval: Duration,
+    pub max_steps: i64,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+struct EncoderState {
+    detents: i64,
+    pending_steps: i64,
+    acceleration: Option<EncoderAcceleration>,
+    last_detent: Option<(Instant, i64)>,
+    steps_per_detent: i64,
+}
+impl EncoderState {
+    fn turn(&mut self, direction: i64, time: Instant) {
+        self.detents += direction;
+        self.steps_per_detent = match (self.acceleration, self.last_detent) {
+            (Some(acceleration), Some((last, last_direction)))
+                if last_direction == direction
+                    && time.saturating_duration_since(last) <= acceleration.interval =>
+            {
+                (self.steps_per_detent + 1).min(acceleration.max_steps.max(1))
+            }
+            _ => 1,
+        };
+        self.pending_steps += direction * self.steps_per_detent;
+        self.last_detent = Some((time, direction));
+    }
+}
+
+/// Detent counts for every encoder of a device, driven by the reader thread.
+///
+/// Encoders report a turn as a short press of their clockwise or counter clockwise button, each press counts as one
+/// detent.
+pub struct Encoders<D>
+where
+    D: VirpilDeviceDescription,
+{
+    states: HashMap<D::Encoder, EncoderState>,
+}
+impl<D> Default for Encoders<D>
+where
+    D: VirpilDeviceDescription,
+{
+    fn default() -> Self {
+        Self {
+            states: D::Encoder::iter()
+                .map(|encoder| (encoder, EncoderState::default()))
+                .collect(),
+        }
+    }
+}
+impl<D> Encoders<D>
+where
+    D: VirpilDeviceDescription,
+{
+    /// Detents turned since the device was opened, clockwise being positive.
+    pub fn detents(&self, encoder: D::Encoder) -> i64 {
+        self.states[&encoder].detents
+    }
+
+    /// Steps turned since the last call, with acceleration applied.
+    pub fn take_delta(&mut self, encoder: D::Encoder) -> i64 {
+        std::mem::take(&mut self.state_mut(encoder).pending_steps)
+    }
+
+    pub fn acceleration(&self, encoder: D::Encoder) -> Option<EncoderAcceleration> {
+        self.states[&encoder].acceleration
+    }
+
+    pub fn set_acceleration(
+        &mut self,
+        encoder: D::Encoder,
+        acceleration: Option<EncoderAcceleration>,
+    ) -> Option<EncoderAcceleration> {
+        std::mem::replace(&mut self.state_mut(encoder).acceleration, acceleration)
+    }
+
+    /// Counts the pulses that started between two consecutive snapshots.
+    pub fn update(&mut self, previous: &Snapshot<D>, current: &Snapshot<D>)
+    where
+        [(); D::Axis::COUNT]:,
+        [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
+    {
+        if previous.buttons == current.buttons {
+            return;
+        }
+        for (encoder, state) in self.states.iter_mut() {
+            let (clockwise, counter_clockwise) = encoder.to_encoder_buttons();
+            if current.button(clockwise) && !previous.button(clockwise) {
+                state.turn(1, current.time);
+            }
+            if current.button(counter_clockwise) && !previous.button(counter_clockwise) {
+                state.turn(-1, current.time);
+            }
+        }
+    }
+
+    fn state_mut(&mut self, encoder: D::Encoder) -> &mut EncoderState {
+        self.states
+            .get_mut(&encoder)
+            .expect("encoder state missing enum value!")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::left_panel::{LeftPanel, LeftPanelEncoder};
+
+    /// Feeds pulses of [`LeftPanelEncoder::E1`] at millisecond offsets from one start time.
+    struct Knob {
+        encoders: Encoders<LeftPanel>,
+        start: Instant,
+        previous: Snapshot<LeftPanel>,
+    }
+    impl Knob {
+        fn new(acceleration: Option<EncoderAcceleration>) -> Self {
+            let start = Instant::now();
+            let mut encoders = Encoders::default();
+            encoders.set_acceleration(LeftPanelEncoder::E1, acceleration);
+            Self {
+                encoders,
+                start,
+                previous: Snapshot::new(start),
+            }
+        }
+
+        fn report(&mut self, millis: u64, clockwise: bool, counter_clockwise: bool) {
+            let buttons = LeftPanelEncoder::E1.to_encoder_buttons();
+            let pressed: Vec<_> = [(buttons.0, clockwise), (buttons.1, counter_clockwise)]
+                .into_iter()
+                .filter(|(_, pressed)| *pressed)
+                .map(|(button, _)| button)
+                .collect();
+            let current =
+                Snapshot::with_buttons(self.start + Duration::from_millis(millis), &pressed);
+            self.encoders.update(&self.previous, &current);
+            self.previous = current;
+        }
+
+        fn turn(&mut self, millis: u64, direction: i64) {
+            self.report(millis, direction > 0, direction < 0);
+            self.report(millis + 10, false, false);
+        }
+    }
+
+    #[test]
+    fn pulses_count_one_detent_each() {
+        let mut knob = Knob::new(None);
+        knob.turn(0, 1);
+        // Held over several reports it still is one pulse.
+        knob.report(100, true, false);
+        knob.report(110, true, false);
+        knob.report(120, false, false);
+        knob.turn(200, -1);
+        assert_eq!(knob.encoders.detents(LeftPanelEncoder::E1), 1);
+        assert_eq!(knob.encoders.take_delta(LeftPanelEncoder::E1), 1);
+        assert_eq!(knob.encoders.take_delta(LeftPanelEncoder::E1), 0);
+        assert_eq!(knob.encoders.detents(LeftPanelEncoder::E2), 0);
+    }
+
+    #[test]
+    fn fast_turns_accelerate_up_to_the_maximum() {
+        let mut knob = Knob::new(Some(EncoderAcceleration {
+            interval: Duration::from_millis(100),
+            max_steps: 3,
+        }));
+        // Steps 1, 2, 3, 3, then 1 again after a pause.
+        for millis in [0, 50, 100, 150, 400] {
+            knob.turn(millis, 1);
+        }
+        assert_eq!(knob.encoders.take_delta(LeftPanelEncoder::E1), 10);
+        // Turning back starts over.
+        knob.turn(450, -1);
+        knob.turn(500, -1);
+        assert_eq!(knob.encoders.take_delta(LeftPanelEncoder::E1), -3);
+        assert_eq!(knob.encoders.detents(LeftPanelEncoder::E1), 3);
+    }
+}
@@ -0,0 +1,148 @@
+use std::fmt::{Display, Formatter};
+
+use crate::left_panel::LeftPanel;
+use crate::right_panel::RightPanel;
+use crate::right_stick::RightStick;
+use crate::shark_panel::SharkPanel;
+use crate::throttle::Throttle;
+use crate::transport::HidBackend;
+use crate::virpil_device::{
+    physical_devices, DeviceSelector, PhysicalDevice, VirpilDeviceDescription,
+};
+
+/// The built in [`VirpilDeviceDescription`]s.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum KnownDevice {
+    Throttle,
+    SharkPanel,
+    LeftPanel,
+    RightPanel,
+    RightStick,
+}
+impl KnownDevice {
+    pub const ALL: &'static [KnownDevice] = &[
+        KnownDevice::Throttle,
+        KnownDevice::SharkPanel,
+        KnownDevice::LeftPanel,
+        KnownDevice::RightPanel,
+        KnownDevice::RightStick,
+    ];
+
+    pub fn from_pid(product_id: u16) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|known| known.pid() == product_id)
+    }
+
+    pub fn pid(self) -> u16 {
+        match self {
+            KnownDevice::Throttle => Throttle::PID,
+            KnownDevice::SharkPanel => SharkPanel::PID,
+            KnownDevice::LeftPanel => LeftPanel::PID,
+            KnownDevice::RightPanel => RightPanel::PID,
+            KnownDevice::RightStick => RightStick::PID,
+        }
+    }
+}
+
+/// One connected Virpil product as found by [`enumerate_devices`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DeviceRecord {
+    /// PID, serial number and interfaces.
+    pub device: PhysicalDevice,
+    pub product_string: Option<String>,
+    pub usages: Vec<u16>,
+    pub known: Option<KnownDevice>,
+}
+impl DeviceRecord {
+    /// Whether both the input (usage 4) and LED (usage 1) interfaces were found.
+    pub fn is_complete(&self) -> bool {
+        self.usages.contains(&4) && self.usages.contains(&1)
+    }
+
+    /// Selector opening this exact device with [`find_device_by`](crate::virpil_device::find_device_by).
+    pub fn selector(&self) -> DeviceSelector {
+        self.device.selector()
+    }
+}
+impl From<PhysicalDevice> for DeviceRecord {
+    fn from(device: PhysicalDevice) -> Self {
+        let mut usages: Vec<_> = device
+            .interfaces
+            .iter()
+            .map(|interface| interface.usage)
+            .collect();
+        usages.sort_unstable();
+        usages.dedup();
+        Self {
+            product_string: device
+                .interfaces
+                .iter()
+                .find_map(|interface| interface.product_string.clone()),
+            usages,
+            known: KnownDevice::from_pid(device.product_id),
+            device,
+        }
+    }
+}
+impl Display for DeviceRecord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:#06x} {} (serial {}, path {}, usages {:?})",
+            self.device.product_id,
+            self.product_string.as_deref().unwrap_or("<unknown>"),
+            self.device.serial_number.as_deref().unwrap_or("<none>"),
+            self.device.path_prefix,
+            self.usages
+        )?;
+        match self.known {
+            Some(known) => write!(f, " => {:?}", known),
+            None => write!(f, " => no built in description"),
+        }
+    }
+}
+
+/// Lists every connected Virpil product without opening anything.
+pub fn enumerate_devices<B>(hid: &B) -> Vec<DeviceRecord>
+where
+    B: HidBackend,
+{
+    physical_devices(hid)
+        .into_iter()
+        .map(DeviceRecord::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::SimulatedBackend;
+
+    #[test]
+    fn known_devices_round_trip_through_their_pid() {
+        for known in KnownDevice::ALL {
+            assert_eq!(KnownDevice::from_pid(known.pid()), Some(*known));
+        }
+        assert_eq!(KnownDevice::from_pid(0), None);
+    }
+
+    #[test]
+    fn simulated_devices_are_listed_complete() {
+        let backend = SimulatedBackend::new();
+        let throttle = backend.add_device::<Throttle>();
+        let left_panel = backend.add_device::<LeftPanel>();
+        left_panel.unplug();
+        let records = enumerate_devices(&backend);
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record.known, Some(KnownDevice::Throttle));
+        assert_eq!(record.usages, vec![1, 4]);
+        assert!(record.is_complete());
+        assert_eq!(
+            record.device.serial_number.as_deref(),
+            Some(throttle.serial_number())
+        );
+    }
+}
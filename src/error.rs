@@ -0,0 +1,87 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use hidapi::HidError;
+
+use crate::virpil_device::DeviceSelector;
+
+pub type VirpilResult<T> = Result<T, VirpilError>;
+
+#[derive(Debug)]
+pub enum VirpilError {
+    /// No connected device has this PID and matches the selector.
+    DeviceNotFound {
+        product_id: u16,
+        selector: DeviceSelector,
+    },
+    /// The device has no usage 1 interface to send LED reports to.
+    LedInterfaceMissing {
+        path: String,
+    },
+    /// The device has no usage 4 interface to read input reports from.
+    InputInterfaceMissing {
+        path: String,
+    },
+    /// More than one interface with the same usage was grouped into one device.
+    DuplicateInterface {
+        path: String,
+        usage: u16,
+    },
+    UnexpectedUsage {
+        path: String,
+        usage: u16,
+    },
+    Hid(HidError),
+    /// A [`ReplaySpeed::Scaled`](crate::recording::ReplaySpeed::Scaled) that isn't finite and above `0`.
+    InvalidReplaySpeed {
+        scale: f64,
+    },
+    /// The device's worker threads are gone, so nothing can be sent to it anymore. Unlike
+    /// [`DeviceStatus::Disconnected`](crate::virpil_device::DeviceStatus::Disconnected) this doesn't go away on a
+    /// replug, LED changes made while merely unplugged still succeed and are written once the device is back.
+    WorkerStopped,
+}
+impl Display for VirpilError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VirpilError::DeviceNotFound {
+                product_id,
+                selector,
+            } => write!(
+                f,
+                "No device found for pid {:#06x} matching {:?}",
+                product_id, selector
+            ),
+            VirpilError::LedInterfaceMissing { path } => {
+                write!(f, "LED interface missing on {}", path)
+            }
+            VirpilError::InputInterfaceMissing { path } => {
+                write!(f, "Input interface missing on {}", path)
+            }
+            VirpilError::DuplicateInterface { path, usage } => {
+                write!(f, "Duplicate interface with usage {} on {}", usage, path)
+            }
+            VirpilError::UnexpectedUsage { path, usage } => {
+                write!(f, "Unexpected usage {} on {}", usage, path)
+            }
+            VirpilError::Hid(error) => write!(f, "HID error: {}", error),
+            VirpilError::InvalidReplaySpeed { scale } => {
+                write!(f, "Invalid replay speed scale {}", scale)
+            }
+            VirpilError::WorkerStopped => write!(f, "Device worker threads stopped"),
+        }
+    }
+}
+impl Error for VirpilError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            VirpilError::Hid(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+impl From<HidError> for VirpilError {
+    fn from(error: HidError) -> Self {
+        VirpilError::Hid(error)
+    }
+}
@@ -0,0 +1,135 @@
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crossbeam::channel::{unbounded, Receiver, Sender};
+
+use crate::gesture::Gesture;
+use crate::virpil_device::VirpilDeviceDescription;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum InputEventKind<B, A, P> {
+    ButtonPressed(B),
+    ButtonReleased(B),
+    AxisMoved(A, u16),
+    /// A [`VirtualButton`](crate::virtual_button::VirtualButton) entered its range.
+    VirtualButtonPressed(&'static str),
+    VirtualButtonReleased(&'static str),
+    /// A selector reached a new position, see [`VirpilDeviceDescription::SelectorPosition`].
+    SelectorMoved(P),
+    /// A guarded switch was flipped, identified by its switch button.
+    GuardedSwitchActuated {
+        switch: B,
+        on: bool,
+        guard_open: bool,
+    },
+    Gesture(B, Gesture),
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct InputEvent<B, A, P> {
+    /// When the report containing the change was read, or when a timed gesture became due.
+    pub time: Instant,
+    /// Shift layer the change belongs to, see [`ShiftLayers`](crate::layer::ShiftLayers).
+    pub layer: u8,
+    pub kind: InputEventKind<B, A, P>,
+}
+
+pub type DeviceEvent<D> = InputEvent<
+    <D as VirpilDeviceDescription>::Buttons,
+    <D as VirpilDeviceDescription>::Axis,
+    <D as VirpilDeviceDescription>::SelectorPosition,
+>;
+pub type DeviceEventKind<D> = InputEventKind<
+    <D as VirpilDeviceDescription>::Buttons,
+    <D as VirpilDeviceDescription>::Axis,
+    <D as VirpilDeviceDescription>::SelectorPosition,
+>;
+
+/// Fans events out to every subscribed channel, dropping receivers that went away.
+pub struct EventPublisher<E> {
+    subscribers: Mutex<Vec<Sender<E>>>,
+}
+impl<E> Default for EventPublisher<E> {
+    fn default() -> Self {
+        Self {
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+}
+impl<E> EventPublisher<E>
+where
+    E: Clone,
+{
+    pub fn subscribe(&self) -> Receiver<E> {
+        let (sender, receiver) = unbounded();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    pub fn has_subscribers(&self) -> bool {
+        !self.subscribers.lock().unwrap().is_empty()
+    }
+
+    pub fn publish(&self, events: &[E]) {
+        if events.is_empty() {
+            return;
+        }
+        self.subscribers.lock().unwrap().retain(|subscriber| {
+            events
+                .iter()
+                .all(|event| subscriber.send(event.clone()).is_ok())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::simulator::SimulatedBackend;
+    use crate::throttle::{Throttle, ThrottleAxis, ThrottleButtons};
+    use crate::virpil_device::find_device;
+    use crate::LedPower;
+
+    #[test]
+    fn publisher_drops_receivers_that_went_away() {
+        let publisher = EventPublisher::default();
+        let kept = publisher.subscribe();
+        drop(publisher.subscribe());
+        publisher.publish(&[1, 2]);
+        assert_eq!(kept.try_iter().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(publisher.subscribers.lock().unwrap().len(), 1);
+        drop(kept);
+        publisher.publish(&[3]);
+        assert!(!publisher.has_subscribers());
+    }
+
+    #[test]
+    fn device_publishes_changes_of_one_report_together() {
+        let backend = SimulatedBackend::new();
+        let mut simulated = backend.add_device::<Throttle>();
+        let device = find_device::<Throttle, _>(&backend, LedPower::OFF).unwrap();
+        let events = device.subscribe();
+        simulated
+            .set_axis(ThrottleAxis::Slider, 1234)
+            .press(ThrottleButtons::B3)
+            .send_report();
+        let first = events.recv_timeout(Duration::from_secs(1)).unwrap();
+        let second = events.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(
+            first.kind,
+            InputEventKind::AxisMoved(ThrottleAxis::Slider, 1234)
+        );
+        assert_eq!(
+            second.kind,
+            InputEventKind::ButtonPressed(ThrottleButtons::B3)
+        );
+        assert_eq!(first.time, second.time);
+        simulated.release(ThrottleButtons::B3).send_report();
+        assert_eq!(
+            events.recv_timeout(Duration::from_secs(1)).unwrap().kind,
+            InputEventKind::ButtonReleased(ThrottleButtons::B3)
+        );
+    }
+}
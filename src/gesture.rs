@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use strum::{EnumCount, IntoEnumIterator};
+
+use crate::events::{DeviceEventKind, InputEventKind};
+use crate::snapshot::Snapshot;
+use crate::virpil_device::VirpilDeviceDescription;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Gesture {
+    /// Released before [`GestureConfig::long_press`] or the first [`Gesture::HoldRepeat`] and not followed by a second
+    /// tap.
+    ShortPress,
+    /// Held for [`GestureConfig::long_press`], sent while still held.
+    LongPress,
+    /// Pressed again within [`GestureConfig::double_tap`] of a short press, sent on the second press.
+    DoubleTap,
+    /// Sent repeatedly while held, see [`RepeatConfig`].
+    HoldRepeat,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct RepeatConfig {
+    /// How long the button is held before the first repeat.
+    pub delay: Duration,
+    pub interval: Duration,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct GestureConfig {
+    pub long_press: Duration,
+    /// Longest gap between releasing and pressing again that still counts as a double tap.
+    ///
+    /// Short presses are only reported once this has passed, `None` disables double taps so they are reported on
+    /// release.
+    pub double_tap: Option<Duration>,
+    pub repeat: Option<RepeatConfig>,
+}
+impl Default for GestureConfig {
+    fn default() -> Self {
+        Self {
+            long_press: Duration::from_millis(500),
+            double_tap: Some(Duration::from_millis(250)),
+            repeat: None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+struct ButtonGesture {
+    config: Option<GestureConfig>,
+    pressed_at: Option<Instant>,
+    long_press_sent: bool,
+    repeat_sent: bool,
+    next_repeat: Option<Instant>,
+    /// Release time of a short press waiting to become a double tap.
+    pending_tap: Option<Instant>,
+    /// The current press completed a double tap and sends nothing else.
+    second_tap: bool,
+}
+impl ButtonGesture {
+    fn press(&mut self, time: Instant, out: &mut Vec<Gesture>) {
+        self.expire(time, out);
+        self.second_tap = self.pending_tap.take().is_some();
+        if self.second_tap {
+            out.push(Gesture::DoubleTap);
+        }
+        self.pressed_at = Some(time);
+        self.long_press_sent = false;
+        self.repeat_sent = false;
+        self.next_repeat = self
+            .config
+            .and_then(|config| config.repeat)
+            .map(|repeat| time + repeat.delay);
+    }
+
+    fn release(&mut self, time: Instant, out: &mut Vec<Gesture>) {
+        self.expire(time, out);
+        if let Some(config) = self.config {
+            // A press that already repeated was a hold, not a tap.
+            if !self.second_tap && !self.long_press_sent && !self.repeat_sent {
+                match config.double_tap {
+                    Some(_) => self.pending_tap = Some(time),
+                    None => out.push(Gesture::ShortPress),
+                }
+            }
+        }
+        self.pressed_at = None;
+        self.next_repeat = None;
+        self.second_tap = false;
+    }
+
+    /// Sends everything that became due by `now` without the button changing.
+    fn expire(&mut self, now: Instant, out: &mut Vec<Gesture>) {
+        let Some(config) = self.config else {
+            return;
+        };
+        if let (Some(released), Some(double_tap)) = (self.pending_tap, config.double_tap) {
+            if now.saturating_duration_since(released) > double_tap {
+                self.pending_tap = None;
+                out.push(Gesture::ShortPress);
+            }
+        }
+        if let Some(pressed_at) = self.pressed_at {
+            if !self.second_tap
+                && !self.long_press_sent
+                && now.saturating_duration_since(pressed_at) >= config.long_press
+            {
+                self.long_press_sent = true;
+                out.push(Gesture::LongPress);
+            }
+        }
+        if let (Some(next_repeat), Some(repeat)) = (self.next_repeat, config.repeat) {
+            if !self.second_tap && now >= next_repeat {
+                self.next_repeat = Some(now + repeat.interval);
+                self.repeat_sent = true;
+                out.push(Gesture::HoldRepeat);
+            }
+        }
+    }
+}
+
+/// Press timing of every button of a device, driven by the reader thread.
+///
+/// Hold durations are tracked for every button, gestures are only sent for buttons with a [`GestureConfig`].
+pub struct Gestures<D>
+where
+    D: VirpilDeviceDescription,
+{
+    buttons: HashMap<D::Buttons, ButtonGesture>,
+}
+impl<D> Default for Gestures<D>
+where
+    D: VirpilDeviceDescription,
+{
+    fn default() -> Self {
+        Self {
+            buttons: D::Buttons::iter()
+                .map(|button| (button, ButtonGesture::default()))
+                .collect(),
+        }
+    }
+}
+impl<D> Gestures<D>
+where
+    D: VirpilDeviceDescription,
+{
+    pub fn config(&self, button: D::Buttons) -> Option<GestureConfig> {
+        self.buttons[&button].config
+    }
+
+    /// Takes effect from the next press, `None` stops sending gestures for the button.
+    pub fn set_config(
+        &mut self,
+        button: D::Buttons,
+        config: Option<GestureConfig>,
+    ) -> Option<GestureConfig> {
+        let gesture = self.button_mut(button);
+        gesture.pending_tap = None;
+        std::mem::replace(&mut gesture.config, config)
+    }
+
+    /// How long the button has been held as of `now`, `None` while released.
+    pub fn held_for(&self, button: D::Buttons, now: Instant) -> Option<Duration> {
+        self.buttons[&button]
+            .pressed_at
+            .map(|pressed_at| now.saturating_duration_since(pressed_at))
+    }
+
+    /// Handles the presses and releases between two consecutive snapshots.
+    pub fn update(
+        &mut self,
+        previous: &Snapshot<D>,
+        current: &Snapshot<D>,
+        events: &mut Vec<DeviceEventKind<D>>,
+    ) where
+        [(); D::Axis::COUNT]:,
+        [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
+    {
+        if previous.buttons == current.buttons {
+            self.tick(current.time, events);
+            return;
+        }
+        let mut out = Vec::new();
+        for button in D::Buttons::iter() {
+            let gesture = self.button_mut(button);
+            match (previous.button(button), current.button(button)) {
+                (false, true) => gesture.press(current.time, &mut out),
+                (true, false) => gesture.release(current.time, &mut out),
+                _ => gesture.expire(current.time, &mut out),
+            }
+            events.extend(
+                out.drain(..)
+                    .map(|gesture| InputEventKind::Gesture(button, gesture)),
+            );
+        }
+    }
+
+    /// Sends the long presses, repeats and short presses that became due without a new report.
+    pub fn tick(&mut self, now: Instant, events: &mut Vec<DeviceEventKind<D>>) {
+        let mut out = Vec::new();
+        for button in D::Buttons::iter() {
+            self.button_mut(button).expire(now, &mut out);
+            events.extend(
+                out.drain(..)
+                    .map(|gesture| InputEventKind::Gesture(button, gesture)),
+            );
+        }
+    }
+
+    fn button_mut(&mut self, button: D::Buttons) -> &mut ButtonGesture {
+        self.buttons
+            .get_mut(&button)
+            .expect("button gesture missing enum value!")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::left_panel::{LeftPanel, LeftPanelButtons};
+
+    /// Drives the gestures of `B1` with reports and ticks at millisecond offsets from one start time.
+    struct Timeline {
+        gestures: Gestures<LeftPanel>,
+        start: Instant,
+        previous: Snapshot<LeftPanel>,
+    }
+    impl Timeline {
+        fn new(config: Option<GestureConfig>) -> Self {
+            let start = Instant::now();
+            let mut gestures = Gestures::default();
+            gestures.set_config(LeftPanelButtons::B1, config);
+            Self {
+                gestures,
+                start,
+                previous: Snapshot::new(start),
+            }
+        }
+
+        fn at(&self, millis: u64) -> Instant {
+            self.start + Duration::from_millis(millis)
+        }
+
+        fn report(&mut self, millis: u64, pressed: bool) -> Vec<Gesture> {
+            let held: &[_] = if pressed {
+                &[LeftPanelButtons::B1]
+            } else {
+                &[]
+            };
+            let current = Snapshot::with_buttons(self.at(millis), held);
+            let mut events = Vec::new();
+            self.gestures.update(&self.previous, &current, &mut events);
+            self.previous = current;
+            Self::gestures(events)
+        }
+
+        fn tick(&mut self, millis: u64) -> Vec<Gesture> {
+            let mut events = Vec::new();
+            self.gestures.tick(self.at(millis), &mut events);
+            Self::gestures(events)
+        }
+
+        fn gestures(events: Vec<DeviceEventKind<LeftPanel>>) -> Vec<Gesture> {
+            events
+                .into_iter()
+                .map(|event| match event {
+                    InputEventKind::Gesture(LeftPanelButtons::B1, gesture) => gesture,
+                    event => panic!("unexpected {:?}", event),
+                })
+                .collect()
+        }
+    }
+
+    fn config(double_tap: Option<u64>, repeat: Option<(u64, u64)>) -> Option<GestureConfig> {
+        Some(GestureConfig {
+            long_press: Duration::from_millis(500),
+            double_tap: double_tap.map(Duration::from_millis),
+            repeat: repeat.map(|(delay, interval)| RepeatConfig {
+                delay: Duration::from_millis(delay),
+                interval: Duration::from_millis(interval),
+            }),
+        })
+    }
+
+    #[test]
+    fn short_press_without_double_tap_is_sent_on_release() {
+        let mut timeline = Timeline::new(config(None, None));
+        assert_eq!(timeline.report(0, true), vec![]);
+        assert_eq!(timeline.report(100, false), vec![Gesture::ShortPress]);
+        assert_eq!(timeline.tick(1000), vec![]);
+    }
+
+    #[test]
+    fn short_press_waits_for_the_double_tap_window() {
+        let mut timeline = Timeline::new(config(Some(250), None));
+        timeline.report(0, true);
+        assert_eq!(timeline.report(100, false), vec![]);
+        assert_eq!(timeline.tick(350), vec![]);
+        assert_eq!(timeline.tick(351), vec![Gesture::ShortPress]);
+        assert_eq!(timeline.tick(1000), vec![]);
+    }
+
+    #[test]
+    fn second_press_in_the_window_is_a_double_tap() {
+        let mut timeline = Timeline::new(config(Some(250), Some((300, 100))));
+        timeline.report(0, true);
+        timeline.report(100, false);
+        assert_eq!(timeline.report(300, true), vec![Gesture::DoubleTap]);
+        // The second press sends nothing else, however long it is held.
+        assert_eq!(timeline.tick(1000), vec![]);
+        assert_eq!(timeline.report(1100, false), vec![]);
+        assert_eq!(timeline.tick(2000), vec![]);
+    }
+
+    #[test]
+    fn press_after_the_window_starts_over() {
+        let mut timeline = Timeline::new(config(Some(250), None));
+        timeline.report(0, true);
+        timeline.report(100, false);
+        assert_eq!(timeline.report(400, true), vec![Gesture::ShortPress]);
+        assert_eq!(timeline.report(450, false), vec![]);
+        assert_eq!(timeline.tick(701), vec![Gesture::ShortPress]);
+    }
+
+    #[test]
+    fn long_press_is_sent_while_held() {
+        let mut timeline = Timeline::new(config(Some(250), None));
+        timeline.report(0, true);
+        assert_eq!(timeline.tick(499), vec![]);
+        assert_eq!(timeline.tick(500), vec![Gesture::LongPress]);
+        assert_eq!(timeline.tick(600), vec![]);
+        assert_eq!(timeline.report(700, false), vec![]);
+        assert_eq!(timeline.tick(1000), vec![]);
+    }
+
+    #[test]
+    fn hold_repeats_after_the_delay() {
+        let mut timeline = Timeline::new(config(None, Some((300, 100))));
+        timeline.report(0, true);
+        assert_eq!(timeline.tick(299), vec![]);
+        assert_eq!(timeline.tick(300), vec![Gesture::HoldRepeat]);
+        assert_eq!(timeline.tick(399), vec![]);
+        assert_eq!(timeline.tick(400), vec![Gesture::HoldRepeat]);
+        assert_eq!(
+            timeline.tick(500),
+            vec![Gesture::LongPress, Gesture::HoldRepeat]
+        );
+        assert_eq!(timeline.report(550, false), vec![]);
+        assert_eq!(timeline.tick(700), vec![]);
+    }
+
+    #[test]
+    fn repeated_presses_are_not_taps() {
+        let mut timeline = Timeline::new(config(Some(250), Some((100, 50))));
+        timeline.report(0, true);
+        assert_eq!(timeline.tick(100), vec![Gesture::HoldRepeat]);
+        assert_eq!(timeline.report(120, false), vec![]);
+        // Not the first tap of a double tap either.
+        assert_eq!(timeline.report(300, true), vec![]);
+        assert_eq!(timeline.report(320, false), vec![]);
+        assert_eq!(timeline.tick(571), vec![Gesture::ShortPress]);
+
+        let mut timeline = Timeline::new(config(None, Some((100, 50))));
+        timeline.report(0, true);
+        assert_eq!(timeline.tick(100), vec![Gesture::HoldRepeat]);
+        assert_eq!(timeline.report(120, false), vec![]);
+        assert_eq!(timeline.tick(1000), vec![]);
+    }
+
+    #[test]
+    fn hold_time_is_tracked_without_a_config() {
+        let mut timeline = Timeline::new(None);
+        assert_eq!(timeline.report(100, true), vec![]);
+        assert_eq!(
+            timeline
+                .gestures
+                .held_for(LeftPanelButtons::B1, timeline.at(350)),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(timeline.tick(1000), vec![]);
+        assert_eq!(timeline.report(1100, false), vec![]);
+        assert_eq!(
+            timeline
+                .gestures
+                .held_for(LeftPanelButtons::B1, timeline.at(1200)),
+            None
+        );
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+
+use strum::{EnumCount, IntoEnumIterator};
+
+use crate::events::{DeviceEventKind, InputEventKind};
+use crate::snapshot::Snapshot;
+use crate::virpil_device::{ToGuardedSwitchButtons, VirpilDeviceDescription};
+
+/// What happens when a guarded switch is flipped while its guard is closed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub enum GuardPolicy {
+    /// Accept the actuation, marking it as done with the guard closed.
+    #[default]
+    Flag,
+    /// Keep the previous switch state as if the switch had not moved. Once the guard opens the accepted state silently
+    /// follows the switch, only flips made with the guard open are reported.
+    Ignore,
+}
+
+/// A guard and the switch under it. The guard button is pressed while the guard is open.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub struct GuardedSwitchState {
+    pub guard_open: bool,
+    /// The switch as accepted under the [`GuardPolicy`], which may differ from the raw button while ignoring.
+    pub on: bool,
+    /// Whether the last accepted actuation happened with the guard open, `None` before the first one.
+    pub actuated_with_guard_open: Option<bool>,
+}
+
+/// Guard and switch state of every guarded switch of a device, driven by the reader thread.
+pub struct GuardedSwitches<D>
+where
+    D: VirpilDeviceDescription,
+{
+    states: HashMap<D::GuardedSwitch, (GuardedSwitchState, GuardPolicy)>,
+}
+impl<D> Default for GuardedSwitches<D>
+where
+    D: VirpilDeviceDescription,
+{
+    fn default() -> Self {
+        Self {
+            states: D::GuardedSwitch::iter()
+                .map(|switch| (switch, Default::default()))
+                .collect(),
+        }
+    }
+}
+impl<D> GuardedSwitches<D>
+where
+    D: VirpilDeviceDescription,
+{
+    pub fn state(&self, switch: D::GuardedSwitch) -> GuardedSwitchState {
+        self.states[&switch].0
+    }
+
+    pub fn policy(&self, switch: D::GuardedSwitch) -> GuardPolicy {
+        self.states[&switch].1
+    }
+
+    pub fn set_policy(&mut self, switch: D::GuardedSwitch, policy: GuardPolicy) -> GuardPolicy {
+        let (_, old) = self
+            .states
+            .get_mut(&switch)
+            .expect("guarded switch state missing enum value!");
+        std::mem::replace(old, policy)
+    }
+
+    /// Applies the guard before the switch, so opening the guard and flipping the switch in one report counts as
+    /// actuated with the guard open.
+    pub fn update(
+        &mut self,
+        previous: &Snapshot<D>,
+        current: &Snapshot<D>,
+        events: &mut Vec<DeviceEventKind<D>>,
+    ) where
+        [(); D::Axis::COUNT]:,
+        [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
+    {
+        if previous.buttons == current.buttons {
+            return;
+        }
+        for switch in D::GuardedSwitch::iter() {
+            let (guard, button) = switch.to_guarded_switch_buttons();
+            let (state, policy) = self
+                .states
+                .get_mut(&switch)
+                .expect("guarded switch state missing enum value!");
+            state.guard_open = current.button(guard);
+            let on = current.button(button);
+            if on == state.on {
+                continue;
+            }
+            if *policy == GuardPolicy::Ignore {
+                if !state.guard_open {
+                    continue;
+                }
+                if previous.button(button) == on {
+                    // Flipped while the guard was closed, nobody touched it with the guard open.
+                    state.on = on;
+                    continue;
+                }
+            }
+            state.on = on;
+            state.actuated_with_guard_open = Some(state.guard_open);
+            events.push(InputEventKind::GuardedSwitchActuated {
+                switch: button,
+                on,
+                guard_open: state.guard_open,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::left_panel::{LeftPanel, LeftPanelButtons, LeftPanelGuardedSwitch};
+
+    fn snapshot(guard_open: bool, on: bool) -> Snapshot<LeftPanel> {
+        let pressed: Vec<_> = [
+            (LeftPanelButtons::T7Guard, guard_open),
+            (LeftPanelButtons::T7, on),
+        ]
+        .into_iter()
+        .filter(|(_, pressed)| *pressed)
+        .map(|(button, _)| button)
+        .collect();
+        Snapshot::with_buttons(Instant::now(), &pressed)
+    }
+
+    /// Feeds `(guard_open, on)` reports, returning the accepted state and the actuations after each.
+    fn run(
+        policy: GuardPolicy,
+        reports: &[(bool, bool)],
+    ) -> Vec<(GuardedSwitchState, Vec<DeviceEventKind<LeftPanel>>)> {
+        let mut switches = GuardedSwitches::<LeftPanel>::default();
+        switches.set_policy(LeftPanelGuardedSwitch::T7, policy);
+        let mut previous = snapshot(false, false);
+        reports
+            .iter()
+            .map(|(guard_open, on)| {
+                let current = snapshot(*guard_open, *on);
+                let mut events = Vec::new();
+                switches.update(&previous, &current, &mut events);
+                previous = current;
+                (switches.state(LeftPanelGuardedSwitch::T7), events)
+            })
+            .collect()
+    }
+
+    fn state(guard_open: bool, on: bool, with_guard_open: Option<bool>) -> GuardedSwitchState {
+        GuardedSwitchState {
+            guard_open,
+            on,
+            actuated_with_guard_open: with_guard_open,
+        }
+    }
+
+    fn actuated(on: bool, guard_open: bool) -> Vec<DeviceEventKind<LeftPanel>> {
+        vec![InputEventKind::GuardedSwitchActuated {
+            switch: LeftPanelButtons::T7,
+            on,
+            guard_open,
+        }]
+    }
+
+    #[test]
+    fn flag_with_guard_open() {
+        assert_eq!(
+            run(
+                GuardPolicy::Flag,
+                &[(true, false), (true, true), (true, false)]
+            ),
+            vec![
+                (state(true, false, None), vec![]),
+                (state(true, true, Some(true)), actuated(true, true)),
+                (state(true, false, Some(true)), actuated(false, true)),
+            ]
+        );
+    }
+
+    #[test]
+    fn flag_with_guard_closed() {
+        assert_eq!(
+            run(GuardPolicy::Flag, &[(false, true), (false, false)]),
+            vec![
+                (state(false, true, Some(false)), actuated(true, false)),
+                (state(false, false, Some(false)), actuated(false, false)),
+            ]
+        );
+    }
+
+    #[test]
+    fn ignore_with_guard_open() {
+        assert_eq!(
+            run(
+                GuardPolicy::Ignore,
+                &[(true, false), (true, true), (true, false)]
+            ),
+            vec![
+                (state(true, false, None), vec![]),
+                (state(true, true, Some(true)), actuated(true, true)),
+                (state(true, false, Some(true)), actuated(false, true)),
+            ]
+        );
+    }
+
+    #[test]
+    fn ignore_with_guard_closed() {
+        assert_eq!(
+            run(
+                GuardPolicy::Ignore,
+                &[
+                    (false, true),
+                    (false, false),
+                    (false, true),
+                    (true, true),
+                    (true, false)
+                ]
+            ),
+            vec![
+                (state(false, false, None), vec![]),
+                (state(false, false, None), vec![]),
+                (state(false, false, None), vec![]),
+                // Opening the guard catches up with the switch flipped while it was closed without an actuation.
+                (state(true, true, None), vec![]),
+                (state(true, false, Some(true)), actuated(false, true)),
+            ]
+        );
+    }
+
+    #[test]
+    fn opening_the_guard_and_flipping_in_one_report_counts_as_open() {
+        assert_eq!(
+            run(GuardPolicy::Ignore, &[(true, true)]),
+            vec![(state(true, true, Some(true)), actuated(true, true))]
+        );
+    }
+}
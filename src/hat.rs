@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+
+use strum::{EnumCount, IntoEnumIterator};
+
+use crate::snapshot::Snapshot;
+use crate::virpil_device::{ToHatButtons, VirpilDeviceDescription};
+
+/// The buttons making up one hat switch.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct HatButtons<B> {
+    pub up: B,
+    pub right: B,
+    pub down: B,
+    pub left: B,
+    pub press: B,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum HatDirection {
+    Centered,
+    North,
+    NorthEast,
+    East,
+    SouthEast,
+    South,
+    SouthWest,
+    West,
+    NorthWest,
+}
+impl HatDirection {
+    /// Combines the pressed directions, opposite directions cancel each other out.
+    pub fn from_buttons(up: bool, right: bool, down: bool, left: bool) -> Self {
+        let vertical = up as i8 - down as i8;
+        let horizontal = right as i8 - left as i8;
+        match (vertical, horizontal) {
+            (1, 0) => HatDirection::North,
+            (1, 1) => HatDirection::NorthEast,
+            (0, 1) => HatDirection::East,
+            (-1, 1) => HatDirection::SouthEast,
+            (-1, 0) => HatDirection::South,
+            (-1, -1) => HatDirection::SouthWest,
+            (0, -1) => HatDirection::West,
+            (1, -1) => HatDirection::NorthWest,
+            _ => HatDirection::Centered,
+        }
+    }
+
+    /// Clockwise from north in degrees like a HID POV, `None` when centered.
+    pub fn angle(&self) -> Option<u16> {
+        match self {
+            HatDirection::Centered => None,
+            HatDirection::North => Some(0),
+            HatDirection::NorthEast => Some(45),
+            HatDirection::East => Some(90),
+            HatDirection::SouthEast => Some(135),
+            HatDirection::South => Some(180),
+            HatDirection::SouthWest => Some(225),
+            HatDirection::West => Some(270),
+            HatDirection::NorthWest => Some(315),
+        }
+    }
+
+    pub fn is_diagonal(&self) -> bool {
+        matches!(
+            self,
+            HatDirection::NorthEast
+                | HatDirection::SouthEast
+                | HatDirection::SouthWest
+                | HatDirection::NorthWest
+        )
+    }
+
+    /// The `(vertical, horizontal)` directions a diagonal is made of.
+    fn components(&self) -> (HatDirection, HatDirection) {
+        match self {
+            HatDirection::NorthEast => (HatDirection::North, HatDirection::East),
+            HatDirection::SouthEast => (HatDirection::South, HatDirection::East),
+            HatDirection::SouthWest => (HatDirection::South, HatDirection::West),
+            HatDirection::NorthWest => (HatDirection::North, HatDirection::West),
+            direction => (*direction, *direction),
+        }
+    }
+}
+
+/// What a hat reports while two adjacent directions are held, the profile's `pov_*` settings.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub enum DiagonalHandling {
+    /// Report the diagonal, an 8-way POV.
+    #[default]
+    EightWay,
+    /// Report only the up or down part, a 4-way POV.
+    PreferVertical,
+    /// Report only the left or right part, a 4-way POV.
+    PreferHorizontal,
+    /// Keep reporting whichever part was held first, a 4-way POV that does not flicker while rolling over a corner.
+    KeepPrevious,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct HatState {
+    pub direction: HatDirection,
+    pub pressed: bool,
+}
+impl Default for HatState {
+    fn default() -> Self {
+        Self {
+            direction: HatDirection::Centered,
+            pressed: false,
+        }
+    }
+}
+
+/// Direction and press of every hat of a device, driven by the reader thread.
+pub struct Hats<D>
+where
+    D: VirpilDeviceDescription,
+{
+    states: HashMap<D::Hat, (HatState, DiagonalHandling)>,
+}
+impl<D> Default for Hats<D>
+where
+    D: VirpilDeviceDescription,
+{
+    fn default() -> Self {
+        Self {
+            states: D::Hat::iter()
+                .map(|hat| (hat, Default::default()))
+                .collect(),
+        }
+    }
+}
+impl<D> Hats<D>
+where
+    D: VirpilDeviceDescription,
+{
+    pub fn state(&self, hat: D::Hat) -> HatState {
+        self.states[&hat].0
+    }
+
+    pub fn diagonal_handling(&self, hat: D::Hat) -> DiagonalHandling {
+        self.states[&hat].1
+    }
+
+    /// Takes effect from the next report.
+    pub fn set_diagonal_handling(
+        &mut self,
+        hat: D::Hat,
+        handling: DiagonalHandling,
+    ) -> DiagonalHandling {
+        let (_, old) = self
+            .states
+            .get_mut(&hat)
+            .expect("hat state missing enum value!");
+        std::mem::replace(old, handling)
+    }
+
+    pub fn update(&mut self, snapshot: &Snapshot<D>)
+    where
+        [(); D::Axis::COUNT]:,
+        [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
+    {
+        for (hat, (state, handling)) in self.states.iter_mut() {
+            let buttons = hat.to_hat_buttons();
+            let direction = HatDirection::from_buttons(
+                snapshot.button(buttons.up),
+                snapshot.button(buttons.right),
+                snapshot.button(buttons.down),
+                snapshot.button(buttons.left),
+            );
+            let (vertical, horizontal) = direction.components();
+            state.direction = match handling {
+                DiagonalHandling::EightWay => direction,
+                DiagonalHandling::PreferVertical => vertical,
+                DiagonalHandling::PreferHorizontal => horizontal,
+                DiagonalHandling::KeepPrevious if direction.is_diagonal() => {
+                    if state.direction == horizontal {
+                        horizontal
+                    } else {
+                        vertical
+                    }
+                }
+                DiagonalHandling::KeepPrevious => direction,
+            };
+            state.pressed = snapshot.button(buttons.press);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::throttle::{Throttle, ThrottleHat};
+
+    #[test]
+    fn from_buttons_combines_directions() {
+        use HatDirection::*;
+        for (up, right, down, left, expected) in [
+            (false, false, false, false, Centered),
+            (true, false, false, false, North),
+            (true, true, false, false, NorthEast),
+            (false, true, false, false, East),
+            (false, true, true, false, SouthEast),
+            (false, false, true, false, South),
+            (false, false, true, true, SouthWest),
+            (false, false, false, true, West),
+            (true, false, false, true, NorthWest),
+            (true, false, true, false, Centered),
+            (false, true, false, true, Centered),
+            (true, true, true, false, East),
+            (true, true, false, true, North),
+            (true, false, true, true, West),
+            (false, true, true, true, South),
+            (true, true, true, true, Centered),
+        ] {
+            assert_eq!(
+                HatDirection::from_buttons(up, right, down, left),
+                expected,
+                "{} {} {} {}",
+                up,
+                right,
+                down,
+                left
+            );
+        }
+    }
+
+    #[test]
+    fn angles_follow_hid_pov() {
+        assert_eq!(HatDirection::Centered.angle(), None);
+        assert_eq!(HatDirection::North.angle(), Some(0));
+        assert_eq!(HatDirection::SouthEast.angle(), Some(135));
+        assert_eq!(HatDirection::NorthWest.angle(), Some(315));
+        assert!(HatDirection::SouthWest.is_diagonal());
+        assert!(!HatDirection::West.is_diagonal());
+    }
+
+    /// Rolls the index hat from north over the north east corner to east and back.
+    fn roll(handling: DiagonalHandling) -> Vec<HatDirection> {
+        let buttons = ThrottleHat::IndexHat.to_hat_buttons();
+        let mut hats = Hats::<Throttle>::default();
+        hats.set_diagonal_handling(ThrottleHat::IndexHat, handling);
+        [
+            vec![buttons.up],
+            vec![buttons.up, buttons.right],
+            vec![buttons.right],
+            vec![buttons.up, buttons.right],
+            vec![],
+        ]
+        .iter()
+        .map(|pressed| {
+            hats.update(&Snapshot::with_buttons(Instant::now(), pressed));
+            hats.state(ThrottleHat::IndexHat).direction
+        })
+        .collect()
+    }
+
+    #[test]
+    fn diagonal_handling() {
+        use HatDirection::*;
+        for (handling, expected) in [
+            (
+                DiagonalHandling::EightWay,
+                [North, NorthEast, East, NorthEast, Centered],
+            ),
+            (
+                DiagonalHandling::PreferVertical,
+                [North, North, East, North, Centered],
+            ),
+            (
+                DiagonalHandling::PreferHorizontal,
+                [North, East, East, East, Centered],
+            ),
+            (
+                DiagonalHandling::KeepPrevious,
+                [North, North, East, East, Centered],
+            ),
+        ] {
+            assert_eq!(roll(handling), expected, "{:?}", handling);
+        }
+    }
+
+    #[test]
+    fn press_is_tracked_separately() {
+        let buttons = ThrottleHat::IndexHat.to_hat_buttons();
+        let mut hats = Hats::<Throttle>::default();
+        hats.update(&Snapshot::with_buttons(
+            Instant::now(),
+            &[buttons.press, buttons.down],
+        ));
+        assert_eq!(
+            hats.state(ThrottleHat::IndexHat),
+            HatState {
+                direction: HatDirection::South,
+                pressed: true,
+            }
+        );
+        assert_eq!(hats.state(ThrottleHat::WheelHat), HatState::default());
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use strum::{EnumCount, IntoEnumIterator};
+
+use crate::events::{DeviceEventKind, InputEventKind};
+use crate::snapshot::Snapshot;
+use crate::virpil_device::VirpilDeviceDescription;
+
+/// The layer nothing is shifted into.
+pub const BASE_LAYER: u8 = 0;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ShiftMode {
+    /// The layer is active while the modifier is held.
+    Hold,
+    /// Each press of the modifier switches the layer on or off.
+    Toggle,
+}
+
+/// A button that shifts the other buttons into `layer`, the Configurator's `Shift` column.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ShiftModifier<B> {
+    pub button: B,
+    pub layer: u8,
+    pub mode: ShiftMode,
+}
+
+/// Active shift layer of a device and the layer every button was pressed in, driven by the reader thread.
+///
+/// A held [`ShiftMode::Hold`] modifier wins over a toggled layer, the most recently added one if several are held.
+pub struct ShiftLayers<D>
+where
+    D: VirpilDeviceDescription,
+{
+    modifiers: Vec<ShiftModifier<D::Buttons>>,
+    held: Vec<D::Buttons>,
+    toggled: Option<u8>,
+    layer: u8,
+    pressed_in: HashMap<D::Buttons, u8>,
+}
+impl<D> Default for ShiftLayers<D>
+where
+    D: VirpilDeviceDescription,
+{
+    fn default() -> Self {
+        Self {
+            modifiers: Vec::new(),
+            held: Vec::new(),
+            toggled: None,
+            layer: BASE_LAYER,
+            pressed_in: HashMap::new(),
+        }
+    }
+}
+impl<D> ShiftLayers<D>
+where
+    D: VirpilDeviceDescription,
+{
+    pub fn layer(&self) -> u8 {
+        self.layer
+    }
+
+    /// Adds `modifier`, replacing any modifier on the same button. Takes effect from the next press.
+    pub fn insert(
+        &mut self,
+        modifier: ShiftModifier<D::Buttons>,
+    ) -> Option<ShiftModifier<D::Buttons>> {
+        let old = self.remove(modifier.button);
+        self.modifiers.push(modifier);
+        old
+    }
+
+    /// Removes the modifier on `button`, dropping its layer if it was active. The layer change shows from the next
+    /// report.
+    pub fn remove(&mut self, button: D::Buttons) -> Option<ShiftModifier<D::Buttons>> {
+        let index = self
+            .modifiers
+            .iter()
+            .position(|modifier| modifier.button == button)?;
+        let modifier = self.modifiers.remove(index);
+        self.held.retain(|held| *held != button);
+        if modifier.mode == ShiftMode::Toggle && self.toggled == Some(modifier.layer) {
+            self.toggled = None;
+        }
+        Some(modifier)
+    }
+
+    /// Layer `button` was pressed in, kept after release.
+    pub fn pressed_in(&self, button: D::Buttons) -> u8 {
+        self.pressed_in.get(&button).copied().unwrap_or(BASE_LAYER)
+    }
+
+    /// Applies modifier changes between two snapshots, then records the layer of every newly pressed button.
+    /// Returns whether the active layer changed.
+    pub fn update(&mut self, previous: &Snapshot<D>, current: &Snapshot<D>) -> bool
+    where
+        [(); D::Axis::COUNT]:,
+        [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
+    {
+        let changed = previous.buttons != current.buttons;
+        if changed {
+            for modifier in &self.modifiers {
+                match (
+                    previous.button(modifier.button),
+                    current.button(modifier.button),
+                ) {
+                    (false, true) => match modifier.mode {
+                        ShiftMode::Hold => self.held.push(modifier.button),
+                        ShiftMode::Toggle if self.toggled == Some(modifier.layer) => {
+                            self.toggled = None
+                        }
+                        ShiftMode::Toggle => self.toggled = Some(modifier.layer),
+                    },
+                    (true, false) => self.held.retain(|held| *held != modifier.button),
+                    _ => {}
+                }
+            }
+        }
+        let held = self.modifiers.iter().rev().find(|modifier| {
+            modifier.mode == ShiftMode::Hold && self.held.contains(&modifier.button)
+        });
+        let layer = held
+            .map(|modifier| modifier.layer)
+            .or(self.toggled)
+            .unwrap_or(BASE_LAYER);
+        if changed {
+            for button in D::Buttons::iter() {
+                if current.button(button) && !previous.button(button) {
+                    self.pressed_in.insert(button, layer);
+                }
+            }
+        }
+        std::mem::replace(&mut self.layer, layer) != layer
+    }
+
+    /// Button events belong to the layer their button was pressed in, everything else to the active layer.
+    pub fn event_layer(&self, kind: &DeviceEventKind<D>) -> u8 {
+        match kind {
+            InputEventKind::ButtonPressed(button)
+            | InputEventKind::ButtonReleased(button)
+            | InputEventKind::Gesture(button, _) => self.pressed_in(*button),
+            _ => self.layer,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::left_panel::{LeftPanel, LeftPanelButtons};
+
+    use LeftPanelButtons::{B1, B2, B3, B4};
+
+    /// Feeds reports with exactly `held` pressed, returning whether the layer changed.
+    fn report(
+        layers: &mut ShiftLayers<LeftPanel>,
+        previous: &mut Snapshot<LeftPanel>,
+        held: &[LeftPanelButtons],
+    ) -> bool {
+        let current = Snapshot::with_buttons(Instant::now(), held);
+        let changed = layers.update(previous, &current);
+        *previous = current;
+        changed
+    }
+
+    fn modifier(
+        button: LeftPanelButtons,
+        layer: u8,
+        mode: ShiftMode,
+    ) -> ShiftModifier<LeftPanelButtons> {
+        ShiftModifier {
+            button,
+            layer,
+            mode,
+        }
+    }
+
+    #[test]
+    fn held_modifier_shifts_while_held() {
+        let mut layers = ShiftLayers::default();
+        let mut previous = Snapshot::new(Instant::now());
+        layers.insert(modifier(B1, 1, ShiftMode::Hold));
+        assert!(report(&mut layers, &mut previous, &[B1]));
+        assert_eq!(layers.layer(), 1);
+        assert!(!report(&mut layers, &mut previous, &[B1, B2]));
+        assert!(report(&mut layers, &mut previous, &[B2]));
+        assert_eq!(layers.layer(), BASE_LAYER);
+        // B2 still belongs to the layer it was pressed in.
+        assert_eq!(layers.pressed_in(B2), 1);
+        assert_eq!(layers.event_layer(&InputEventKind::ButtonReleased(B2)), 1);
+        assert_eq!(
+            layers.event_layer(&InputEventKind::VirtualButtonPressed("slider")),
+            BASE_LAYER
+        );
+    }
+
+    #[test]
+    fn toggled_modifier_switches_on_every_press() {
+        let mut layers = ShiftLayers::default();
+        let mut previous = Snapshot::new(Instant::now());
+        layers.insert(modifier(B1, 2, ShiftMode::Toggle));
+        let layers_seen: Vec<_> = [&[B1][..], &[], &[B1], &[]]
+            .iter()
+            .map(|held| {
+                report(&mut layers, &mut previous, held);
+                layers.layer()
+            })
+            .collect();
+        assert_eq!(layers_seen, vec![2, 2, BASE_LAYER, BASE_LAYER]);
+    }
+
+    #[test]
+    fn newest_held_modifier_wins() {
+        let mut layers = ShiftLayers::default();
+        let mut previous = Snapshot::new(Instant::now());
+        layers.insert(modifier(B1, 1, ShiftMode::Toggle));
+        layers.insert(modifier(B2, 2, ShiftMode::Hold));
+        layers.insert(modifier(B3, 3, ShiftMode::Hold));
+        report(&mut layers, &mut previous, &[B1]);
+        report(&mut layers, &mut previous, &[B3]);
+        assert_eq!(layers.layer(), 3);
+        report(&mut layers, &mut previous, &[B2, B3]);
+        assert_eq!(layers.layer(), 3);
+        report(&mut layers, &mut previous, &[B2]);
+        assert_eq!(layers.layer(), 2);
+        report(&mut layers, &mut previous, &[]);
+        assert_eq!(layers.layer(), 1);
+    }
+
+    #[test]
+    fn removed_modifier_drops_its_layer() {
+        let mut layers = ShiftLayers::default();
+        let mut previous = Snapshot::new(Instant::now());
+        layers.insert(modifier(B1, 1, ShiftMode::Hold));
+        layers.insert(modifier(B4, 4, ShiftMode::Toggle));
+        report(&mut layers, &mut previous, &[B1]);
+        assert_eq!(layers.remove(B1), Some(modifier(B1, 1, ShiftMode::Hold)));
+        assert!(report(&mut layers, &mut previous, &[B1]));
+        assert_eq!(layers.layer(), BASE_LAYER);
+        report(&mut layers, &mut previous, &[B4]);
+        assert_eq!(layers.layer(), 4);
+        layers.remove(B4);
+        assert!(report(&mut layers, &mut previous, &[B4]));
+        assert_eq!(layers.layer(), BASE_LAYER);
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use strum::EnumCount;
+
+use crate::snapshot::Snapshot;
+use crate::virpil_device::VirpilDeviceDescription;
+use crate::Color;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum BindingMode {
+    /// On while the button is held.
+    Held,
+    /// Switches between on and off on every press.
+    Toggle,
+}
+
+/// Makes an LED follow a button.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct LedBinding<B> {
+    pub button: B,
+    pub mode: BindingMode,
+    pub on: Color,
+    pub off: Color,
+}
+impl<B> LedBinding<B> {
+    pub fn held(button: B, on: Color, off: Color) -> Self {
+        Self {
+            button,
+            mode: BindingMode::Held,
+            on,
+            off,
+        }
+    }
+
+    pub fn toggle(button: B, on: Color, off: Color) -> Self {
+        Self {
+            button,
+            mode: BindingMode::Toggle,
+            on,
+            off,
+        }
+    }
+
+    fn color(&self, on: bool) -> Color {
+        if on {
+            self.on
+        } else {
+            self.off
+        }
+    }
+}
+
+/// LED bindings of a device and whether each is on, driven by the reader thread.
+pub struct LedBindings<D>
+where
+    D: VirpilDeviceDescription,
+{
+    bindings: HashMap<D::Led, (LedBinding<D::Buttons>, bool)>,
+}
+impl<D> Default for LedBindings<D>
+where
+    D: VirpilDeviceDescription,
+{
+    fn default() -> Self {
+        Self {
+            bindings: HashMap::new(),
+        }
+    }
+}
+impl<D> LedBindings<D>
+where
+    D: VirpilDeviceDescription,
+{
+    /// Binds `led`, returning the old binding and the color to show now. Toggles start off, held bindings follow
+    /// the button's state in `snapshot`.
+    pub fn insert(
+        &mut self,
+        led: D::Led,
+        binding: LedBinding<D::Buttons>,
+        snapshot: &Snapshot<D>,
+    ) -> (Option<LedBinding<D::Buttons>>, Color)
+    where
+        [(); D::Axis::COUNT]:,
+        [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
+    {
+        let on = binding.mode == BindingMode::Held && snapshot.button(binding.button);
+        let old = self
+            .bindings
+            .insert(led, (binding, on))
+            .map(|(binding, _)| binding);
+        (old, binding.color(on))
+    }
+
+    pub fn remove(&mut self, led: D::Led) -> Option<LedBinding<D::Buttons>> {
+        self.bindings.remove(&led).map(|(binding, _)| binding)
+    }
+
+    pub fn get(&self, led: D::Led) -> Option<LedBinding<D::Buttons>> {
+        self.bindings.get(&led).map(|(binding, _)| *binding)
+    }
+
+    /// Returns the LEDs whose color changed between two consecutive snapshots.
+    pub fn update(&mut self, previous: &Snapshot<D>, current: &Snapshot<D>) -> Vec<(D::Led, Color)>
+    where
+        [(); D::Axis::COUNT]:,
+        [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
+    {
+        let mut changes = Vec::new();
+        if previous.buttons == current.buttons {
+            return changes;
+        }
+        for (led, (binding, on)) in self.bindings.iter_mut() {
+            let pressed = current.button(binding.button);
+            let next = match binding.mode {
+                BindingMode::Held => pressed,
+                BindingMode::Toggle if pressed && !previous.button(binding.button) => !*on,
+                BindingMode::Toggle => *on,
+            };
+            if next != *on {
+                *on = next;
+                changes.push((*led, binding.color(next)));
+            }
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::left_panel::{LeftPanel, LeftPanelButtons, LeftPanelLed};
+    use crate::LedPower;
+
+    const ON: Color = LedPower::FULL_GREEN;
+    const OFF: Color = LedPower::FULL_BLUE;
+
+    fn snapshot(pressed: bool) -> Snapshot<LeftPanel> {
+        let held: &[_] = if pressed {
+            &[LeftPanelButtons::B1]
+        } else {
+            &[]
+        };
+        Snapshot::with_buttons(Instant::now(), held)
+    }
+
+    /// Binds `LeftPanelLed::B1` to `LeftPanelButtons::B1` and plays `presses`, returning the color after each
+    /// report, `None` where it did not change.
+    fn colors(
+        binding: LedBinding<LeftPanelButtons>,
+        start_pressed: bool,
+        presses: &[bool],
+    ) -> (Color, Vec<Option<Color>>) {
+        let mut bindings = LedBindings::<LeftPanel>::default();
+        let mut previous = snapshot(start_pressed);
+        let (_, start) = bindings.insert(LeftPanelLed::B1, binding, &previous);
+        let changes = presses
+            .iter()
+            .map(|pressed| {
+                let current = snapshot(*pressed);
+                let changes = bindings.update(&previous, &current);
+                previous = current;
+                changes.first().map(|(_, color)| *color)
+            })
+            .collect();
+        (start, changes)
+    }
+
+    #[test]
+    fn held_bindings_follow_the_button() {
+        let held = LedBinding::held(LeftPanelButtons::B1, ON, OFF);
+        assert_eq!(
+            colors(held, false, &[true, true, false]),
+            (OFF, vec![Some(ON), None, Some(OFF)])
+        );
+        assert_eq!(colors(held, true, &[false]), (ON, vec![Some(OFF)]));
+    }
+
+    #[test]
+    fn toggle_bindings_switch_on_every_press() {
+        let toggle = LedBinding::toggle(LeftPanelButtons::B1, ON, OFF);
+        assert_eq!(
+            colors(toggle, true, &[false, true, false, true]),
+            (OFF, vec![None, Some(ON), None, Some(OFF)])
+        );
+    }
+
+    #[test]
+    fn rebinding_returns_the_old_binding() {
+        let mut bindings = LedBindings::<LeftPanel>::default();
+        let held = LedBinding::held(LeftPanelButtons::B1, ON, OFF);
+        let toggle = LedBinding::toggle(LeftPanelButtons::B2, ON, OFF);
+        assert_eq!(
+            bindings.insert(LeftPanelLed::B1, held, &snapshot(false)).0,
+            None
+        );
+        assert_eq!(
+            bindings
+                .insert(LeftPanelLed::B1, toggle, &snapshot(false))
+                .0,
+            Some(held)
+        );
+        assert_eq!(bindings.get(LeftPanelLed::B1), Some(toggle));
+        assert_eq!(bindings.remove(LeftPanelLed::B1), Some(toggle));
+        assert!(bindings
+            .update(&snapshot(false), &snapshot(true))
+            .is_empty());
+    }
+}
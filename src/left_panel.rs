@@ -1,6 +1,11 @@
 use strum::{EnumCount, EnumIter};
 
-use crate::virpil_device::{ToAxisIndex, ToButtonIndex, VirpilDeviceDescription};
+use crate::axis::AxisInfo;
+use crate::hat::HatButtons;
+use crate::virpil_device::{
+    ToAxisIndex, ToAxisInfo, ToButtonIndex, ToEncoderButtons, ToGuardedSwitchButtons, ToHatButtons,
+    ToSelectorPositions, VirpilDeviceDescription,
+};
 use crate::{BoardType, ToBoardAndLedNumber};
 
 #[derive(Debug, Copy, Clone)]
@@ -9,6 +14,11 @@ impl VirpilDeviceDescription for LeftPanel {
     type Led = LeftPanelLed;
     type Buttons = LeftPanelButtons;
     type Axis = LeftPanelAxis;
+    type Encoder = LeftPanelEncoder;
+    type Hat = LeftPanelHat;
+    type Selector = LeftPanelSelector;
+    type SelectorPosition = LeftPanelSelectorPosition;
+    type GuardedSwitch = LeftPanelGuardedSwitch;
     const PID: u16 = 0x025B;
 }
 
@@ -98,3 +108,92 @@ impl ToAxisIndex for LeftPanelAxis {
         unreachable!()
     }
 }
+impl ToAxisInfo for LeftPanelAxis {
+    fn to_axis_info(&self) -> AxisInfo {
+        unreachable!()
+    }
+}
+
+#[derive(EnumCount, EnumIter, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum LeftPanelEncoder {
+    E1,
+    E2,
+    E3,
+}
+impl ToEncoderButtons<LeftPanelButtons> for LeftPanelEncoder {
+    fn to_encoder_buttons(&self) -> (LeftPanelButtons, LeftPanelButtons) {
+        match self {
+            LeftPanelEncoder::E1 => (
+                LeftPanelButtons::E1Clockwise,
+                LeftPanelButtons::E1CounterClockwise,
+            ),
+            LeftPanelEncoder::E2 => (
+                LeftPanelButtons::E2Clockwise,
+                LeftPanelButtons::E2CounterClockwise,
+            ),
+            LeftPanelEncoder::E3 => (
+                LeftPanelButtons::E3Clockwise,
+                LeftPanelButtons::E3CounterClockwise,
+            ),
+        }
+    }
+}
+
+#[derive(EnumCount, EnumIter, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum LeftPanelHat {}
+impl ToHatButtons<LeftPanelButtons> for LeftPanelHat {
+    fn to_hat_buttons(&self) -> HatButtons<LeftPanelButtons> {
+        unreachable!()
+    }
+}
+
+#[derive(EnumCount, EnumIter, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum LeftPanelSelector {
+    Gear,
+}
+impl ToSelectorPositions<LeftPanelButtons, LeftPanelSelectorPosition> for LeftPanelSelector {
+    fn to_selector_positions(&self) -> &'static [(LeftPanelSelectorPosition, LeftPanelButtons)] {
+        match self {
+            LeftPanelSelector::Gear => &[
+                (
+                    LeftPanelSelectorPosition::Gear(LeftPanelGear::Up),
+                    LeftPanelButtons::GearUp,
+                ),
+                (
+                    LeftPanelSelectorPosition::Gear(LeftPanelGear::Middle),
+                    LeftPanelButtons::GearMiddle,
+                ),
+                (
+                    LeftPanelSelectorPosition::Gear(LeftPanelGear::Down),
+                    LeftPanelButtons::GearDown,
+                ),
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum LeftPanelSelectorPosition {
+    Gear(LeftPanelGear),
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum LeftPanelGear {
+    Up,
+    Middle,
+    Down,
+}
+
+#[derive(EnumCount, EnumIter, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum LeftPanelGuardedSwitch {
+    T7,
+    T8,
+}
+impl ToGuardedSwitchButtons<LeftPanelButtons> for LeftPanelGuardedSwitch {
+    fn to_guarded_switch_buttons(&self) -> (LeftPanelButtons, LeftPanelButtons) {
+        match self {
+            LeftPanelGuardedSwitch::T7 => (LeftPanelButtons::T7Guard, LeftPanelButtons::T7),
+            LeftPanelGuardedSwitch::T8 => (LeftPanelButtons::T8Guard, LeftPanelButtons::T8),
+        }
+    }
+}
@@ -1,29 +1,50 @@
 #![allow(incomplete_features)]
 #![feature(generic_const_exprs, split_array)]
 
-use std::io::{stdin, Read};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use std::thread::{sleep, spawn};
-use std::time::{Duration, Instant, SystemTime};
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::Duration;
 
 use ctrlc::set_handler;
-use hidapi::{HidApi, HidDevice, HidResult};
+use hidapi::{HidApi, HidResult};
 use strum::IntoEnumIterator;
 
-use crate::left_panel::{LeftPanel, LeftPanelButtons, LeftPanelLed};
+use crate::animation::Effect;
+use crate::enumeration::enumerate_devices;
+use crate::left_panel::{LeftPanel, LeftPanelLed};
 use crate::right_panel::{RightPanel, RightPanelLed};
 use crate::right_stick::{RightStick, RightStickLed};
 use crate::shark_panel::{SharkPanel, SharkPanelLed};
 use crate::throttle::{Throttle, ThrottleLed};
-use crate::virpil_device::{find_device, VirpilDevice, VirpilDeviceDescription};
-
+use crate::transport::HidTransport;
+use crate::virpil_device::{find_reconnecting_device, VirpilDeviceDescription};
+
+pub mod actions;
+pub mod animation;
+pub mod axis;
+pub mod encoder;
+pub mod enumeration;
+pub mod error;
+pub mod events;
+pub mod gesture;
+pub mod guarded_switch;
+pub mod hat;
+pub mod layer;
+pub mod led_binding;
 pub mod left_panel;
+pub mod recording;
 pub mod right_panel;
 pub mod right_stick;
+pub mod selector;
 pub mod shark_panel;
+pub mod simulator;
+pub mod snapshot;
+pub mod statistics;
 pub mod throttle;
+pub mod transport;
 pub mod virpil_device;
+pub mod virtual_button;
 
 #[repr(u8)]
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -47,6 +68,16 @@ pub enum LedPower {
     Full = 3,
 }
 impl LedPower {
+    /// Maps the low two bits to a level, `0` being off.
+    pub fn from_level(level: u8) -> Self {
+        match level & 0b11 {
+            0 => LedPower::Zero,
+            1 => LedPower::Thirty,
+            2 => LedPower::Sixty,
+            _ => LedPower::Full,
+        }
+    }
+
     pub const OFF: Color = [LedPower::Zero, LedPower::Zero, LedPower::Zero];
     pub const FULL_RED: Color = [LedPower::Full, LedPower::Zero, LedPower::Zero];
     pub const FULL_YELLOW: Color = [LedPower::Full, LedPower::Full, LedPower::Zero];
@@ -90,6 +121,17 @@ fn color_to_byte(color: [LedPower; 3]) -> u8 {
     0b_1000_0000 | color[0] as u8 | (color[1] as u8) << 2 | (color[2] as u8) << 4
 }
 
+fn color_from_byte(byte: u8) -> Option<Color> {
+    if byte & 0b_1000_0000 == 0 {
+        return None;
+    }
+    Some([
+        LedPower::from_level(byte),
+        LedPower::from_level(byte >> 2),
+        LedPower::from_level(byte >> 4),
+    ])
+}
+
 fn command_id_for_command(board_type: BoardType, led_number: u8) -> u8 {
     match board_type {
         BoardType::Default => 0,
@@ -100,17 +142,26 @@ fn command_id_for_command(board_type: BoardType, led_number: u8) -> u8 {
 }
 
 fn packet_for_command(board_type: BoardType, led_number: u8, color: [LedPower; 3]) -> [u8; 38] {
+    packet_for_leds(board_type, &[(led_number, color)])
+}
+
+/// One report filling the slot of every LED in `leds`, all on `board_type`. It carries the command id of the lowest
+/// LED number.
+fn packet_for_leds(board_type: BoardType, leds: &[(u8, Color)]) -> [u8; 38] {
     let mut out = [0; 38];
     out[0] = 0x02;
     out[1] = board_type as u8;
-    out[2] = command_id_for_command(board_type, led_number);
-    out[led_number as usize + 4] = color_to_byte(color);
+    let first = leds.iter().map(|(led_number, _)| *led_number).min();
+    out[2] = command_id_for_command(board_type, first.unwrap_or(0));
+    for (led_number, color) in leds {
+        out[*led_number as usize + 4] = color_to_byte(*color);
+    }
     out[37] = 0xf0;
     out
 }
 
-fn send_command(
-    device: &HidDevice,
+fn send_command<T: HidTransport>(
+    device: &T,
     board_type: BoardType,
     led_number: u8,
     color: [LedPower; 3],
@@ -129,98 +180,35 @@ fn main() {
     })
     .unwrap();
 
-    let hid = HidApi::new().unwrap();
+    let hid = Arc::new(Mutex::new(HidApi::new().unwrap()));
+    for record in enumerate_devices(&*hid.lock().unwrap()) {
+        println!("Found {}", record);
+    }
 
-    let mut shark_panel = find_device::<SharkPanel>(&hid, LedPower::FULL_RED).unwrap();
-    let mut throttle = find_device::<Throttle>(&hid, LedPower::FULL_RED).unwrap();
-    let mut left_panel = find_device::<LeftPanel>(&hid, LedPower::FULL_RED).unwrap();
-    let mut right_panel = find_device::<RightPanel>(&hid, LedPower::FULL_RED).unwrap();
-    let mut right_stick = find_device::<RightStick>(&hid, LedPower::FULL_RED).unwrap();
+    let shark_panel = find_reconnecting_device::<SharkPanel, _>(&hid, LedPower::FULL_RED).unwrap();
+    let throttle = find_reconnecting_device::<Throttle, _>(&hid, LedPower::FULL_RED).unwrap();
+    let left_panel = find_reconnecting_device::<LeftPanel, _>(&hid, LedPower::FULL_RED).unwrap();
+    let right_panel = find_reconnecting_device::<RightPanel, _>(&hid, LedPower::FULL_RED).unwrap();
+    let right_stick = find_reconnecting_device::<RightStick, _>(&hid, LedPower::FULL_RED).unwrap();
 
-    type Device = SharkPanel;
-    let device: &mut VirpilDevice<Device> = &mut shark_panel;
-    let mut color_index = 0;
     const TIME: Duration = Duration::from_millis(500);
+    let effect = Effect::ColorCycle { step: TIME };
+    for led in SharkPanelLed::iter() {
+        shark_panel.animate(led, effect);
+    }
+    for led in ThrottleLed::iter() {
+        throttle.animate(led, effect);
+    }
+    for led in LeftPanelLed::iter() {
+        left_panel.animate(led, effect);
+    }
+    for led in RightPanelLed::iter() {
+        right_panel.animate(led, effect);
+    }
+    for led in RightStickLed::iter() {
+        right_stick.animate(led, effect);
+    }
     while !stop.load(Ordering::Relaxed) {
-        color_index = (color_index + 1) % LedPower::COLOR_PROGRESSION.len();
-        let color = LedPower::COLOR_PROGRESSION[color_index];
-
-        // for led in <<Device as VirpilDeviceDescription>::Led as IntoEnumIterator>::iter() {
-        //     println!("Set {:?}", led);
-        //     let _ = stdin().read(&mut [0; 128]).unwrap();
-        //     device.set_led(led, color).unwrap();
-        //     sleep(Duration::from_millis(100));
-        // }
-
-        let mut times = [Instant::now(); 6];
-        let mut times_ref = times.iter_mut();
-
-        *times_ref.next().unwrap() = Instant::now();
-        for led in SharkPanelLed::iter() {
-            if let Some(err) = shark_panel.set_led(led, color).err() {
-                eprintln!("Error encountered: {}", err)
-            }
-        }
-        *times_ref.next().unwrap() = Instant::now();
-        for led in ThrottleLed::iter() {
-            if let Some(err) = throttle.set_led(led, color).err() {
-                eprintln!("Error encountered: {}", err)
-            }
-        }
-        *times_ref.next().unwrap() = Instant::now();
-        for led in LeftPanelLed::iter() {
-            if let Some(err) = left_panel.set_led(led, color).err() {
-                eprintln!("Error encountered: {}", err)
-            }
-        }
-        *times_ref.next().unwrap() = Instant::now();
-        for led in RightPanelLed::iter() {
-            if let Some(err) = right_panel.set_led(led, color).err() {
-                eprintln!("Error encountered: {}", err)
-            }
-        }
-        *times_ref.next().unwrap() = Instant::now();
-        for led in RightStickLed::iter() {
-            if let Some(err) = right_stick.set_led(led, color).err() {
-                eprintln!("Error encountered: {}", err)
-            }
-        }
-        *times_ref.next().unwrap() = Instant::now();
-
-        drop(times_ref);
-        for index in 0..times.len() - 1 {
-            println!(
-                "Time {}: {:?}",
-                index,
-                times[index + 1].duration_since(times[index])
-            );
-        }
-        println!(
-            "Total:{:?}",
-            times[times.len() - 1].duration_since(times[0])
-        );
-        sleep(TIME);
-        while shark_panel.send_queue_size() > 0 {}
-        while throttle.send_queue_size() > 0 {}
-        while left_panel.send_queue_size() > 0 {}
-        while right_panel.send_queue_size() > 0 {}
-        while right_stick.send_queue_size() > 0 {}
+        sleep(Duration::from_millis(50));
     }
-
-    // left.join().unwrap();
-
-    // let control = virpil_devices
-    //     .iter()
-    //     .find(|dev| dev.product_id() == PANEL_1_PID[0] && dev.usage() == 4)
-    //     .unwrap()
-    //     .open_device(&hid)
-    //     .unwrap();
-    // let mut buf = [0; 128];
-    // // buf[0] = 2;
-    // // let read = other_throttle.get_feature_report(&mut buf).unwrap();
-    // // println!("{:?}", &buf[0..read]);
-    // loop {
-    //     let read = control.read(&mut buf).unwrap();
-    //     println!("{:?}", &buf[0..read]);
-    // }
 }
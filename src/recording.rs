@@ -0,0 +1,565 @@
+//! Capture and replay of raw device traffic.
+//!
+//! A session file is plain text. The first line is the header `virpil-recording 1`, every following
+//! line is one packet:
+//!
+//! ```text
+//! <micros> <pid> <device> <kind> <data>
+//! ```
+//!
+//! - `micros`: decimal microseconds since the [`Recorder`] was created.
+//! - `pid`: product id of the device as 4 hex digits.
+//! - `device`: which of several devices with that pid sent the packet, `s:<serial number>`, `p:<path>` or `-` if
+//!   unknown. Whitespace and `%` in the serial number or path are written as `%` and two hex digits.
+//! - `kind`: `I` for an input report read by the reader thread, `L` for an LED feature report sent by the writer.
+//! - `data`: the raw bytes as lowercase hex with no separators, the input report id included.
+//!
+//! Blank lines and lines starting with `#` are ignored.
+
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use hidapi::HidResult;
+use strum::EnumCount;
+
+use crate::error::{VirpilError, VirpilResult};
+use crate::transport::HidTransport;
+use crate::virpil_device::{DeviceSelector, VirpilDevice, VirpilDeviceDescription};
+use crate::Color;
+
+pub const RECORDING_HEADER: &str = "virpil-recording 1";
+
+/// Longest a [`ReplayTransport`] read blocks before returning an empty report.
+const REPLAY_POLL: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum PacketKind {
+    Input,
+    Led,
+}
+impl PacketKind {
+    fn tag(self) -> char {
+        match self {
+            PacketKind::Input => 'I',
+            PacketKind::Led => 'L',
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RecordedPacket {
+    pub elapsed: Duration,
+    pub product_id: u16,
+    /// [`DeviceSelector::Any`] if the recording didn't know the device.
+    pub device: DeviceSelector,
+    pub kind: PacketKind,
+    pub data: Vec<u8>,
+}
+
+/// Shared handle writing packets to a session file, clone it to record several devices into one file.
+#[derive(Clone)]
+pub struct Recorder {
+    inner: Arc<RecorderInner>,
+}
+struct RecorderInner {
+    start: Instant,
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn new(mut writer: impl Write + Send + 'static) -> std::io::Result<Self> {
+        writeln!(writer, "{}", RECORDING_HEADER)?;
+        Ok(Self {
+            inner: Arc::new(RecorderInner {
+                start: Instant::now(),
+                writer: Mutex::new(Box::new(writer)),
+            }),
+        })
+    }
+
+    pub fn record(&self, product_id: u16, device: &DeviceSelector, kind: PacketKind, data: &[u8]) {
+        let elapsed = self.inner.start.elapsed();
+        let mut line = format!(
+            "{} {:04x} {} {} ",
+            elapsed.as_micros(),
+            product_id,
+            device_field(device),
+            kind.tag()
+        );
+        for byte in data {
+            write!(line, "{:02x}", byte).unwrap();
+        }
+        if let Err(error) = writeln!(self.inner.writer.lock().unwrap(), "{}", line) {
+            eprintln!("Error writing recording: {}", error);
+        }
+    }
+
+    pub fn flush(&self) -> std::io::Result<()> {
+        self.inner.writer.lock().unwrap().flush()
+    }
+}
+
+/// A session file loaded into memory.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Recording {
+    pub packets: Vec<RecordedPacket>,
+}
+impl Recording {
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Self::parse(BufReader::new(File::open(path)?))
+    }
+
+    pub fn parse(reader: impl BufRead) -> std::io::Result<Self> {
+        let mut lines = reader.lines();
+        let header = lines.next().transpose()?;
+        if header.as_deref().map(str::trim) != Some(RECORDING_HEADER) {
+            return Err(invalid_data("missing recording header"));
+        }
+        let mut packets = Vec::new();
+        for (number, line) in lines.enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            packets.push(
+                parse_packet(line)
+                    .ok_or_else(|| invalid_data(&format!("bad packet on line {}", number + 2)))?,
+            );
+        }
+        Ok(Self { packets })
+    }
+
+    /// Packets of `product_id` recorded from `device`, [`DeviceSelector::Any`] taking every device.
+    pub fn packets_for<'a>(
+        &'a self,
+        product_id: u16,
+        device: &'a DeviceSelector,
+        kind: PacketKind,
+    ) -> impl Iterator<Item = &'a RecordedPacket> {
+        self.packets.iter().filter(move |packet| {
+            packet.product_id == product_id
+                && (*device == DeviceSelector::Any || packet.device == *device)
+                && packet.kind == kind
+        })
+    }
+
+    /// Builds a `D` fed by this recording's input reports for `D::PID`.
+    pub fn replay<D>(
+        &self,
+        speed: ReplaySpeed,
+        starting_color: Color,
+    ) -> VirpilResult<(VirpilDevice<D, ReplayTransport>, ReplayProgress)>
+    where
+        D: VirpilDeviceDescription + 'static,
+        [(); D::Axis::COUNT]:,
+        [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
+    {
+        self.replay_device(&DeviceSelector::Any, speed, starting_color)
+    }
+
+    /// Like [`Recording::replay`] but only replays the `D` recorded as `device`, for sessions with several identical
+    /// devices.
+    pub fn replay_device<D>(
+        &self,
+        device: &DeviceSelector,
+        speed: ReplaySpeed,
+        starting_color: Color,
+    ) -> VirpilResult<(VirpilDevice<D, ReplayTransport>, ReplayProgress)>
+    where
+        D: VirpilDeviceDescription + 'static,
+        [(); D::Axis::COUNT]:,
+        [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
+    {
+        let (state_read, progress) = ReplayTransport::new(self, D::PID, device, speed)?;
+        let (led_write, _) = ReplayTransport::new(&Recording::default(), D::PID, device, speed)?;
+        Ok((
+            VirpilDevice::new(state_read, led_write, starting_color)?,
+            progress,
+        ))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ReplaySpeed {
+    Original,
+    /// Multiplier on the original speed, `2.0` plays twice as fast. Must be finite and above `0`.
+    Scaled(f64),
+    /// No delay between packets.
+    Unlimited,
+}
+
+/// How far a replay has gotten.
+#[derive(Debug, Clone)]
+pub struct ReplayProgress {
+    played: Arc<AtomicUsize>,
+    total: usize,
+}
+impl ReplayProgress {
+    pub fn played(&self) -> usize {
+        self.played.load(Ordering::Acquire)
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.played() >= self.total
+    }
+
+    /// Blocks until every packet has been handed to the reader thread.
+    pub fn wait(&self) {
+        while !self.is_finished() {
+            sleep(REPLAY_POLL);
+        }
+    }
+}
+
+/// [`HidTransport`] returning recorded input reports at their recorded times and discarding feature reports.
+pub struct ReplayTransport {
+    reports: Vec<(Duration, Vec<u8>)>,
+    played: Arc<AtomicUsize>,
+    start: Mutex<Option<Instant>>,
+}
+impl ReplayTransport {
+    /// Fails with [`VirpilError::InvalidReplaySpeed`] for a [`ReplaySpeed::Scaled`] that isn't finite and above `0`.
+    pub fn new(
+        recording: &Recording,
+        product_id: u16,
+        device: &DeviceSelector,
+        speed: ReplaySpeed,
+    ) -> VirpilResult<(Self, ReplayProgress)> {
+        if let ReplaySpeed::Scaled(scale) = speed {
+            if !scale.is_finite() || scale <= 0.0 {
+                return Err(VirpilError::InvalidReplaySpeed { scale });
+            }
+        }
+        let first = recording
+            .packets_for(product_id, device, PacketKind::Input)
+            .next()
+            .map(|packet| packet.elapsed)
+            .unwrap_or_default();
+        let reports: Vec<_> = recording
+            .packets_for(product_id, device, PacketKind::Input)
+            .map(|packet| {
+                let offset = packet.elapsed.saturating_sub(first);
+                let offset = match speed {
+                    ReplaySpeed::Original => offset,
+                    ReplaySpeed::Scaled(scale) => offset.div_f64(scale),
+                    ReplaySpeed::Unlimited => Duration::ZERO,
+                };
+                (offset, packet.data.clone())
+            })
+            .collect();
+        let played = Arc::new(AtomicUsize::new(0));
+        let progress = ReplayProgress {
+            played: played.clone(),
+            total: reports.len(),
+        };
+        Ok((
+            Self {
+                reports,
+                played,
+                start: Mutex::new(None),
+            },
+            progress,
+        ))
+    }
+}
+impl HidTransport for ReplayTransport {
+    fn read(&self, buffer: &mut [u8]) -> HidResult<usize> {
+        let start = *self.start.lock().unwrap().get_or_insert_with(Instant::now);
+        let index = self.played.load(Ordering::Acquire);
+        let (offset, report) = match self.reports.get(index) {
+            Some(next) => next,
+            None => {
+                sleep(REPLAY_POLL);
+                return Ok(0);
+            }
+        };
+        let elapsed = start.elapsed();
+        if *offset > elapsed {
+            let wait = *offset - elapsed;
+            sleep(wait.min(REPLAY_POLL));
+            if wait > REPLAY_POLL {
+                return Ok(0);
+            }
+        }
+        let count = report.len().min(buffer.len());
+        buffer[..count].copy_from_slice(&report[..count]);
+        self.played.store(index + 1, Ordering::Release);
+        Ok(count)
+    }
+
+    fn send_feature_report(&self, _data: &[u8]) -> HidResult<()> {
+        Ok(())
+    }
+
+    fn get_product_string(&self) -> HidResult<Option<String>> {
+        Ok(Some("Replay".to_owned()))
+    }
+}
+
+fn parse_packet(line: &str) -> Option<RecordedPacket> {
+    let mut fields = line.split_whitespace();
+    let elapsed = Duration::from_micros(fields.next()?.parse().ok()?);
+    let product_id = u16::from_str_radix(fields.next()?, 16).ok()?;
+    let device = parse_device_field(fields.next()?)?;
+    let kind = match fields.next()? {
+        "I" => PacketKind::Input,
+        "L" => PacketKind::Led,
+        _ => return None,
+    };
+    let hex = fields.next().unwrap_or("");
+    if fields.next().is_some() {
+        return None;
+    }
+    let data = hex
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [high, low] => Some(hex_digit(*high)? << 4 | hex_digit(*low)?),
+            _ => None,
+        })
+        .collect::<Option<_>>()?;
+    Some(RecordedPacket {
+        elapsed,
+        product_id,
+        device,
+        kind,
+        data,
+    })
+}
+
+fn device_field(device: &DeviceSelector) -> String {
+    let (prefix, value) = match device {
+        DeviceSelector::Any => return "-".to_owned(),
+        DeviceSelector::SerialNumber(serial_number) => ("s:", serial_number),
+        DeviceSelector::Path(path) => ("p:", path),
+    };
+    let mut out = prefix.to_owned();
+    for char in value.chars() {
+        if char.is_whitespace() || char == '%' {
+            let mut bytes = [0; 4];
+            for byte in char.encode_utf8(&mut bytes).bytes() {
+                write!(out, "%{:02x}", byte).unwrap();
+            }
+        } else {
+            out.push(char);
+        }
+    }
+    out
+}
+
+fn parse_device_field(field: &str) -> Option<DeviceSelector> {
+    if field == "-" {
+        return Some(DeviceSelector::Any);
+    }
+    let (prefix, escaped) = (field.get(..2)?, field.get(2..)?);
+    let mut bytes = Vec::with_capacity(escaped.len());
+    let mut rest = escaped.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        match (byte, tail) {
+            (b'%', [high, low, tail @ ..]) => {
+                bytes.push(hex_digit(*high)? << 4 | hex_digit(*low)?);
+                rest = tail;
+            }
+            (b'%', _) => return None,
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    let value = String::from_utf8(bytes).ok()?;
+    match prefix {
+        "s:" => Some(DeviceSelector::SerialNumber(value)),
+        "p:" => Some(DeviceSelector::Path(value)),
+        _ => None,
+    }
+}
+
+fn hex_digit(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|value| value as u8)
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::left_panel::{LeftPanel, LeftPanelButtons};
+    use crate::simulator::{wait_until, SimulatedBackend, SimulatedDevice};
+    use crate::LedPower;
+
+    /// Session file kept in memory, readable while the [`Recorder`] still holds it.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+    impl Write for SharedBuffer {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(data)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn recorded_packets_parse_back() {
+        let buffer = SharedBuffer::default();
+        let recorder = Recorder::new(buffer.clone()).unwrap();
+        let serial_number = DeviceSelector::SerialNumber("A 1%".to_owned());
+        let path = DeviceSelector::Path("IOService:/VPC Throttle@14200000".to_owned());
+        recorder.record(
+            0x825b,
+            &serial_number,
+            PacketKind::Input,
+            &[0x02, 0x00, 0xff],
+        );
+        recorder.record(0x825b, &path, PacketKind::Led, &[]);
+        recorder.record(0x825b, &DeviceSelector::Any, PacketKind::Led, &[0x01]);
+        recorder.flush().unwrap();
+        let recording = Recording::parse(&buffer.0.lock().unwrap()[..]).unwrap();
+        let packets: Vec<_> = recording
+            .packets
+            .iter()
+            .map(|packet| {
+                (
+                    packet.product_id,
+                    packet.device.clone(),
+                    packet.kind,
+                    packet.data.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            packets,
+            vec![
+                (
+                    0x825b,
+                    serial_number,
+                    PacketKind::Input,
+                    vec![0x02, 0x00, 0xff]
+                ),
+                (0x825b, path, PacketKind::Led, vec![]),
+                (0x825b, DeviceSelector::Any, PacketKind::Led, vec![0x01]),
+            ]
+        );
+        assert!(recording.packets[0].elapsed <= recording.packets[1].elapsed);
+    }
+
+    #[test]
+    fn parse_skips_comments_and_rejects_bad_lines() {
+        let session = format!("{}\n\n# comment\n10 825b s:A1 I 02ff\n", RECORDING_HEADER);
+        let recording = Recording::parse(session.as_bytes()).unwrap();
+        assert_eq!(
+            recording.packets,
+            vec![RecordedPacket {
+                elapsed: Duration::from_micros(10),
+                product_id: 0x825b,
+                device: DeviceSelector::SerialNumber("A1".to_owned()),
+                kind: PacketKind::Input,
+                data: vec![0x02, 0xff],
+            }]
+        );
+        for session in [
+            "10 825b - I 02ff\n".to_owned(),
+            format!("{}\n10 825b - X 02ff\n", RECORDING_HEADER),
+            format!("{}\n10 825b - I 02f\n", RECORDING_HEADER),
+            format!("{}\n10 825b - I 02 ff\n", RECORDING_HEADER),
+            format!("{}\n10 825b x:A1 I 02ff\n", RECORDING_HEADER),
+            format!("{}\n10 825b s:A%2 I 02ff\n", RECORDING_HEADER),
+            format!("{}\n10 825b I 02ff\n", RECORDING_HEADER),
+        ] {
+            assert!(Recording::parse(session.as_bytes()).is_err(), "{}", session);
+        }
+    }
+
+    #[test]
+    fn replay_rejects_bad_scales() {
+        let recording = Recording::default();
+        for scale in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                ReplayTransport::new(
+                    &recording,
+                    LeftPanel::PID,
+                    &DeviceSelector::Any,
+                    ReplaySpeed::Scaled(scale)
+                ),
+                Err(VirpilError::InvalidReplaySpeed { .. })
+            ));
+        }
+        assert!(ReplayTransport::new(
+            &recording,
+            LeftPanel::PID,
+            &DeviceSelector::Any,
+            ReplaySpeed::Scaled(0.5)
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn replay_device_only_feeds_that_device() {
+        let backend = SimulatedBackend::new();
+        let mut first = backend.add_device::<LeftPanel>();
+        let mut second = backend.add_device::<LeftPanel>();
+        first.press(LeftPanelButtons::B1);
+        second.press(LeftPanelButtons::B2);
+        let packet = |simulated: &SimulatedDevice<LeftPanel>| RecordedPacket {
+            elapsed: Duration::ZERO,
+            product_id: LeftPanel::PID,
+            device: DeviceSelector::SerialNumber(simulated.serial_number().to_owned()),
+            kind: PacketKind::Input,
+            data: simulated.report(),
+        };
+        let recording = Recording {
+            packets: vec![packet(&first), packet(&second)],
+        };
+        let (device, progress) = recording
+            .replay_device::<LeftPanel>(
+                &DeviceSelector::SerialNumber(second.serial_number().to_owned()),
+                ReplaySpeed::Unlimited,
+                LedPower::OFF,
+            )
+            .unwrap();
+        assert_eq!(progress.total(), 1);
+        progress.wait();
+        assert!(wait_until(|| device.button_state(LeftPanelButtons::B2)));
+        assert!(!device.button_state(LeftPanelButtons::B1));
+    }
+
+    #[test]
+    fn replay_feeds_recorded_reports() {
+        let backend = SimulatedBackend::new();
+        let mut simulated = backend.add_device::<LeftPanel>();
+        simulated.press(LeftPanelButtons::B1);
+        let recording = Recording {
+            packets: vec![RecordedPacket {
+                elapsed: Duration::ZERO,
+                product_id: LeftPanel::PID,
+                device: DeviceSelector::Any,
+                kind: PacketKind::Input,
+                data: simulated.report(),
+            }],
+        };
+        let (device, progress) = recording
+            .replay::<LeftPanel>(ReplaySpeed::Unlimited, LedPower::OFF)
+            .unwrap();
+        assert_eq!(progress.total(), 1);
+        progress.wait();
+        assert!(wait_until(|| device.button_state(LeftPanelButtons::B1)));
+    }
+}
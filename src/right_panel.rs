@@ -1,6 +1,11 @@
 use strum::{EnumCount, EnumIter};
 
-use crate::virpil_device::{ToAxisIndex, ToButtonIndex, VirpilDeviceDescription};
+use crate::axis::AxisInfo;
+use crate::hat::HatButtons;
+use crate::virpil_device::{
+    ToAxisIndex, ToAxisInfo, ToButtonIndex, ToEncoderButtons, ToGuardedSwitchButtons, ToHatButtons,
+    ToSelectorPositions, VirpilDeviceDescription,
+};
 use crate::{BoardType, ToBoardAndLedNumber};
 
 #[derive(Debug, Copy, Clone)]
@@ -9,6 +14,11 @@ impl VirpilDeviceDescription for RightPanel {
     type Led = RightPanelLed;
     type Buttons = RightPanelButtons;
     type Axis = RightPanelAxis;
+    type Encoder = RightPanelEncoder;
+    type Hat = RightPanelHat;
+    type Selector = RightPanelSelector;
+    type SelectorPosition = RightPanelSelectorPosition;
+    type GuardedSwitch = RightPanelGuardedSwitch;
 
     const PID: u16 = 0x0259;
 }
@@ -46,6 +56,11 @@ impl ToAxisIndex for RightPanelAxis {
         *self as u8
     }
 }
+impl ToAxisInfo for RightPanelAxis {
+    fn to_axis_info(&self) -> AxisInfo {
+        AxisInfo::UNIPOLAR
+    }
+}
 
 #[derive(EnumCount, EnumIter, Debug, Copy, Clone, Eq, PartialEq, Hash)]
 #[repr(u8)]
@@ -103,3 +118,110 @@ impl ToButtonIndex for RightPanelButtons {
         *self as u8 - 1
     }
 }
+
+#[derive(EnumCount, EnumIter, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum RightPanelEncoder {
+    E1,
+    E2,
+    E3,
+}
+impl ToEncoderButtons<RightPanelButtons> for RightPanelEncoder {
+    fn to_encoder_buttons(&self) -> (RightPanelButtons, RightPanelButtons) {
+        match self {
+            RightPanelEncoder::E1 => (
+                RightPanelButtons::E1Clockwise,
+                RightPanelButtons::E1CounterClockwise,
+            ),
+            RightPanelEncoder::E2 => (
+                RightPanelButtons::E2Clockwise,
+                RightPanelButtons::E2CounterClockwise,
+            ),
+            RightPanelEncoder::E3 => (
+                RightPanelButtons::E3Clockwise,
+                RightPanelButtons::E3CounterClockwise,
+            ),
+        }
+    }
+}
+
+#[derive(EnumCount, EnumIter, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum RightPanelHat {}
+impl ToHatButtons<RightPanelButtons> for RightPanelHat {
+    fn to_hat_buttons(&self) -> HatButtons<RightPanelButtons> {
+        unreachable!()
+    }
+}
+
+#[derive(EnumCount, EnumIter, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum RightPanelSelector {
+    A1,
+    A2,
+}
+impl ToSelectorPositions<RightPanelButtons, RightPanelSelectorPosition> for RightPanelSelector {
+    fn to_selector_positions(&self) -> &'static [(RightPanelSelectorPosition, RightPanelButtons)] {
+        match self {
+            RightPanelSelector::A1 => &[
+                (
+                    RightPanelSelectorPosition::A1(RightPanelA1::Left),
+                    RightPanelButtons::A1Left,
+                ),
+                (
+                    RightPanelSelectorPosition::A1(RightPanelA1::Middle),
+                    RightPanelButtons::A1Middle,
+                ),
+                (
+                    RightPanelSelectorPosition::A1(RightPanelA1::Right),
+                    RightPanelButtons::A1Right,
+                ),
+            ],
+            RightPanelSelector::A2 => &[
+                (
+                    RightPanelSelectorPosition::A2(RightPanelA2::Left),
+                    RightPanelButtons::A2Left,
+                ),
+                (
+                    RightPanelSelectorPosition::A2(RightPanelA2::Middle),
+                    RightPanelButtons::A2Middle,
+                ),
+                (
+                    RightPanelSelectorPosition::A2(RightPanelA2::Right),
+                    RightPanelButtons::A2Right,
+                ),
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum RightPanelSelectorPosition {
+    A1(RightPanelA1),
+    A2(RightPanelA2),
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum RightPanelA1 {
+    Left,
+    Middle,
+    Right,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum RightPanelA2 {
+    Left,
+    Middle,
+    Right,
+}
+
+#[derive(EnumCount, EnumIter, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum RightPanelGuardedSwitch {
+    T1,
+    T2,
+}
+impl ToGuardedSwitchButtons<RightPanelButtons> for RightPanelGuardedSwitch {
+    fn to_guarded_switch_buttons(&self) -> (RightPanelButtons, RightPanelButtons) {
+        match self {
+            RightPanelGuardedSwitch::T1 => (RightPanelButtons::T1Guard, RightPanelButtons::T1),
+            RightPanelGuardedSwitch::T2 => (RightPanelButtons::T2Guard, RightPanelButtons::T2),
+        }
+    }
+}
@@ -1,6 +1,11 @@
 use strum::{EnumCount, EnumIter};
 
-use crate::virpil_device::{ToAxisIndex, ToButtonIndex, VirpilDeviceDescription};
+use crate::axis::AxisInfo;
+use crate::hat::HatButtons;
+use crate::virpil_device::{
+    ToAxisIndex, ToAxisInfo, ToButtonIndex, ToEncoderButtons, ToGuardedSwitchButtons, ToHatButtons,
+    ToSelectorPositions, VirpilDeviceDescription,
+};
 use crate::{BoardType, ToBoardAndLedNumber};
 
 pub struct RightStick;
@@ -8,6 +13,11 @@ impl VirpilDeviceDescription for RightStick {
     type Led = RightStickLed;
     type Buttons = RightStickButtons;
     type Axis = RightStickAxis;
+    type Encoder = RightStickEncoder;
+    type Hat = RightStickHat;
+    type Selector = RightStickSelector;
+    type SelectorPosition = RightStickSelectorPosition;
+    type GuardedSwitch = RightStickGuardedSwitch;
     const PID: u16 = 0x4130;
 }
 
@@ -78,3 +88,72 @@ impl ToAxisIndex for RightStickAxis {
         *self as u8 - 1
     }
 }
+impl ToAxisInfo for RightStickAxis {
+    fn to_axis_info(&self) -> AxisInfo {
+        match self {
+            RightStickAxis::LowerTrigger => AxisInfo::UNIPOLAR,
+            _ => AxisInfo::CENTERED,
+        }
+    }
+}
+
+#[derive(EnumCount, EnumIter, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum RightStickEncoder {}
+impl ToEncoderButtons<RightStickButtons> for RightStickEncoder {
+    fn to_encoder_buttons(&self) -> (RightStickButtons, RightStickButtons) {
+        unreachable!()
+    }
+}
+
+#[derive(EnumCount, EnumIter, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum RightStickHat {
+    UpperHat,
+    LowerHat,
+    ThumbHat,
+}
+impl ToHatButtons<RightStickButtons> for RightStickHat {
+    fn to_hat_buttons(&self) -> HatButtons<RightStickButtons> {
+        match self {
+            RightStickHat::UpperHat => HatButtons {
+                up: RightStickButtons::UpperHatUp,
+                right: RightStickButtons::UpperHatRight,
+                down: RightStickButtons::UpperHatDown,
+                left: RightStickButtons::UpperHatLeft,
+                press: RightStickButtons::UpperHatPress,
+            },
+            RightStickHat::LowerHat => HatButtons {
+                up: RightStickButtons::LowerHatUp,
+                right: RightStickButtons::LowerHatRight,
+                down: RightStickButtons::LowerHatDown,
+                left: RightStickButtons::LowerHatLeft,
+                press: RightStickButtons::LowerHatPress,
+            },
+            RightStickHat::ThumbHat => HatButtons {
+                up: RightStickButtons::ThumbHatUp,
+                right: RightStickButtons::ThumbHatRight,
+                down: RightStickButtons::ThumbHatDown,
+                left: RightStickButtons::ThumbHatLeft,
+                press: RightStickButtons::ThumpHatPress,
+            },
+        }
+    }
+}
+
+#[derive(EnumCount, EnumIter, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum RightStickSelector {}
+impl ToSelectorPositions<RightStickButtons, RightStickSelectorPosition> for RightStickSelector {
+    fn to_selector_positions(&self) -> &'static [(RightStickSelectorPosition, RightStickButtons)] {
+        unreachable!()
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum RightStickSelectorPosition {}
+
+#[derive(EnumCount, EnumIter, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum RightStickGuardedSwitch {}
+impl ToGuardedSwitchButtons<RightStickButtons> for RightStickGuardedSwitch {
+    fn to_guarded_switch_buttons(&self) -> (RightStickButtons, RightStickButtons) {
+        unreachable!()
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use strum::{EnumCount, IntoEnumIterator};
+
+use crate::events::{DeviceEventKind, InputEventKind};
+use crate::snapshot::Snapshot;
+use crate::virpil_device::{ToSelectorPositions, VirpilDeviceDescription};
+
+/// Last position of every selector of a device, driven by the reader thread.
+///
+/// Rotary switches report no position at all while between detents, the last position is kept until a new one is
+/// reported.
+pub struct Selectors<D>
+where
+    D: VirpilDeviceDescription,
+{
+    positions: HashMap<D::Selector, Option<D::SelectorPosition>>,
+}
+impl<D> Default for Selectors<D>
+where
+    D: VirpilDeviceDescription,
+{
+    fn default() -> Self {
+        Self {
+            positions: D::Selector::iter()
+                .map(|selector| (selector, None))
+                .collect(),
+        }
+    }
+}
+impl<D> Selectors<D>
+where
+    D: VirpilDeviceDescription,
+{
+    /// `None` until a position was reported.
+    pub fn position(&self, selector: D::Selector) -> Option<D::SelectorPosition> {
+        self.positions[&selector]
+    }
+
+    /// Index of the current position in [`ToSelectorPositions::to_selector_positions`].
+    pub fn position_index(&self, selector: D::Selector) -> Option<usize> {
+        let position = self.position(selector)?;
+        selector
+            .to_selector_positions()
+            .iter()
+            .position(|(candidate, _)| *candidate == position)
+    }
+
+    /// Pushes a [`InputEventKind::SelectorMoved`] for every selector that reached a new position.
+    pub fn update(&mut self, snapshot: &Snapshot<D>, events: &mut Vec<DeviceEventKind<D>>)
+    where
+        [(); D::Axis::COUNT]:,
+        [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
+    {
+        for selector in D::Selector::iter() {
+            let current = selector
+                .to_selector_positions()
+                .iter()
+                .find(|(_, button)| snapshot.button(*button))
+                .map(|(position, _)| *position);
+            let position = self
+                .positions
+                .get_mut(&selector)
+                .expect("selector position missing enum value!");
+            if let Some(current) = current {
+                if *position != Some(current) {
+                    *position = Some(current);
+                    events.push(InputEventKind::SelectorMoved(current));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::throttle::{
+        Throttle, ThrottleButtons, ThrottleFlaps, ThrottleMode, ThrottleSelector,
+        ThrottleSelectorPosition,
+    };
+
+    #[test]
+    fn positions_are_kept_between_detents() {
+        let mut selectors = Selectors::<Throttle>::default();
+        let mut events = Vec::new();
+        selectors.update(&Snapshot::with_buttons(Instant::now(), &[]), &mut events);
+        assert_eq!(selectors.position(ThrottleSelector::Mode), None);
+        assert!(events.is_empty());
+
+        selectors.update(
+            &Snapshot::with_buttons(Instant::now(), &[ThrottleButtons::Mode3]),
+            &mut events,
+        );
+        selectors.update(&Snapshot::with_buttons(Instant::now(), &[]), &mut events);
+        assert_eq!(
+            selectors.position(ThrottleSelector::Mode),
+            Some(ThrottleSelectorPosition::Mode(ThrottleMode::M3))
+        );
+        assert_eq!(selectors.position_index(ThrottleSelector::Mode), Some(2));
+
+        selectors.update(
+            &Snapshot::with_buttons(
+                Instant::now(),
+                &[ThrottleButtons::Mode3, ThrottleButtons::FlapsUp],
+            ),
+            &mut events,
+        );
+        assert_eq!(
+            events,
+            vec![
+                InputEventKind::SelectorMoved(ThrottleSelectorPosition::Mode(ThrottleMode::M3)),
+                InputEventKind::SelectorMoved(ThrottleSelectorPosition::Flaps(ThrottleFlaps::Up)),
+            ]
+        );
+        assert_eq!(selectors.position_index(ThrottleSelector::Flaps), Some(2));
+        assert_eq!(selectors.position(ThrottleSelector::Slider), None);
+    }
+}
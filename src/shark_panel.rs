@@ -1,6 +1,11 @@
 use strum::{EnumCount, EnumIter};
 
-use crate::virpil_device::{ToAxisIndex, ToButtonIndex};
+use crate::axis::AxisInfo;
+use crate::hat::HatButtons;
+use crate::virpil_device::{
+    ToAxisIndex, ToAxisInfo, ToButtonIndex, ToEncoderButtons, ToGuardedSwitchButtons, ToHatButtons,
+    ToSelectorPositions,
+};
 use crate::{BoardType, ToBoardAndLedNumber, VirpilDeviceDescription};
 
 pub struct SharkPanel;
@@ -8,6 +13,11 @@ impl VirpilDeviceDescription for SharkPanel {
     type Led = SharkPanelLed;
     type Buttons = SharkPanelButtons;
     type Axis = SharkPanelAxis;
+    type Encoder = SharkPanelEncoder;
+    type Hat = SharkPanelHat;
+    type Selector = SharkPanelSelector;
+    type SelectorPosition = SharkPanelSelectorPosition;
+    type GuardedSwitch = SharkPanelGuardedSwitch;
     const PID: u16 = 0x825D;
 }
 
@@ -99,3 +109,114 @@ impl ToAxisIndex for SharkPanelAxis {
         *self as u8 - 1
     }
 }
+impl ToAxisInfo for SharkPanelAxis {
+    fn to_axis_info(&self) -> AxisInfo {
+        AxisInfo::UNIPOLAR
+    }
+}
+
+#[derive(EnumCount, EnumIter, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum SharkPanelEncoder {
+    E1,
+    E2,
+}
+impl ToEncoderButtons<SharkPanelButtons> for SharkPanelEncoder {
+    fn to_encoder_buttons(&self) -> (SharkPanelButtons, SharkPanelButtons) {
+        match self {
+            SharkPanelEncoder::E1 => (
+                SharkPanelButtons::E1Clockwise,
+                SharkPanelButtons::E1CounterClockwise,
+            ),
+            SharkPanelEncoder::E2 => (
+                SharkPanelButtons::E2Clockwise,
+                SharkPanelButtons::E2CounterClockwise,
+            ),
+        }
+    }
+}
+
+#[derive(EnumCount, EnumIter, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum SharkPanelHat {}
+impl ToHatButtons<SharkPanelButtons> for SharkPanelHat {
+    fn to_hat_buttons(&self) -> HatButtons<SharkPanelButtons> {
+        unreachable!()
+    }
+}
+
+#[derive(EnumCount, EnumIter, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum SharkPanelSelector {
+    Mode,
+    Range,
+}
+impl ToSelectorPositions<SharkPanelButtons, SharkPanelSelectorPosition> for SharkPanelSelector {
+    fn to_selector_positions(&self) -> &'static [(SharkPanelSelectorPosition, SharkPanelButtons)] {
+        match self {
+            SharkPanelSelector::Mode => &[
+                (
+                    SharkPanelSelectorPosition::Mode(SharkPanelMode::Mov),
+                    SharkPanelButtons::ModeMOV,
+                ),
+                (
+                    SharkPanelSelectorPosition::Mode(SharkPanelMode::Fix),
+                    SharkPanelButtons::ModeFIX,
+                ),
+                (
+                    SharkPanelSelectorPosition::Mode(SharkPanelMode::Man),
+                    SharkPanelButtons::ModeMan,
+                ),
+                (
+                    SharkPanelSelectorPosition::Mode(SharkPanelMode::Fail),
+                    SharkPanelButtons::ModeFail,
+                ),
+                (
+                    SharkPanelSelectorPosition::Mode(SharkPanelMode::Nav),
+                    SharkPanelButtons::ModeNav,
+                ),
+            ],
+            SharkPanelSelector::Range => &[
+                (
+                    SharkPanelSelectorPosition::Range(SharkPanelRange::Long),
+                    SharkPanelButtons::RangeLng,
+                ),
+                (
+                    SharkPanelSelectorPosition::Range(SharkPanelRange::Medium),
+                    SharkPanelButtons::RangeMd,
+                ),
+                (
+                    SharkPanelSelectorPosition::Range(SharkPanelRange::Short),
+                    SharkPanelButtons::RangeShort,
+                ),
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum SharkPanelSelectorPosition {
+    Mode(SharkPanelMode),
+    Range(SharkPanelRange),
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum SharkPanelMode {
+    Mov,
+    Fix,
+    Man,
+    Fail,
+    Nav,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum SharkPanelRange {
+    Long,
+    Medium,
+    Short,
+}
+
+#[derive(EnumCount, EnumIter, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum SharkPanelGuardedSwitch {}
+impl ToGuardedSwitchButtons<SharkPanelButtons> for SharkPanelGuardedSwitch {
+    fn to_guarded_switch_buttons(&self) -> (SharkPanelButtons, SharkPanelButtons) {
+        unreachable!()
+    }
+}
@@ -0,0 +1,456 @@
+use std::any::type_name;
+use std::ffi::CString;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crossbeam::channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use hidapi::{HidError, HidResult};
+use strum::EnumCount;
+
+use crate::transport::{HidBackend, HidTransport, InterfaceInfo};
+use crate::virpil_device::{ToAxisIndex, ToButtonIndex, VirpilDeviceDescription, VIRPIL_VID};
+use crate::{color_from_byte, BoardType, Color, ToBoardAndLedNumber};
+
+/// How long a simulated read blocks before returning an empty report so the reader can check for stop.
+pub const SIMULATED_READ_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// Report id byte put in front of reports built by [`SimulatedDevice::send_report`].
+pub const SIMULATED_REPORT_ID: u8 = 0x01;
+
+/// In-memory [`HidBackend`] standing in for any number of [`VirpilDeviceDescription`]s.
+#[derive(Default)]
+pub struct SimulatedBackend {
+    devices: Mutex<Vec<Arc<SimulatedHardware>>>,
+}
+impl SimulatedBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Plugs in a new simulated `D`, returning the handle used to script it.
+    pub fn add_device<D>(&self) -> SimulatedDevice<D>
+    where
+        D: VirpilDeviceDescription,
+    {
+        let mut devices = self.devices.lock().unwrap();
+        let index = devices.len();
+        let product_string = type_name::<D>().rsplit("::").next().unwrap().to_owned();
+        let (input_sender, input_receiver) = unbounded();
+        let hardware = Arc::new(SimulatedHardware {
+            product_id: D::PID,
+            product_string,
+            serial_number: format!("SIM{:04}", index),
+            path_prefix: format!("ffff:{:04x}", index),
+            input_sender,
+            input_receiver,
+            feature_reports: Mutex::new(Vec::new()),
+            feature_reports_changed: Condvar::new(),
+            generation: AtomicU32::new(0),
+            single_led_reports_only: AtomicBool::new(false),
+            refused_led_reports: AtomicUsize::new(0),
+            led_report_delay: Mutex::new(Duration::ZERO),
+        });
+        devices.push(hardware.clone());
+        SimulatedDevice {
+            hardware,
+            axis: vec![0; D::Axis::COUNT],
+            buttons: vec![0; button_bytes::<D>()],
+            _description: PhantomData,
+        }
+    }
+}
+impl HidBackend for SimulatedBackend {
+    type Device = SimulatedTransport;
+
+    fn interfaces(&self) -> Vec<InterfaceInfo> {
+        let mut out = Vec::new();
+        for hardware in self.devices.lock().unwrap().iter() {
+            if !hardware.is_plugged() {
+                continue;
+            }
+            for (number, usage) in [4, 1].into_iter().enumerate() {
+                out.push(InterfaceInfo {
+                    path: CString::new(format!("{}:{:02x}", hardware.path_prefix, number)).unwrap(),
+                    vendor_id: VIRPIL_VID,
+                    product_id: hardware.product_id,
+                    usage,
+                    serial_number: Some(hardware.serial_number.clone()),
+                    product_string: Some(hardware.product_string.clone()),
+                });
+            }
+        }
+        out
+    }
+
+    fn open(&self, interface: &InterfaceInfo) -> HidResult<Self::Device> {
+        let path_prefix = interface.path_prefix();
+        self.devices
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|hardware| {
+                hardware.is_plugged() && path_prefix.as_ref() == Some(&hardware.path_prefix)
+            })
+            .map(|hardware| SimulatedTransport {
+                hardware: hardware.clone(),
+                generation: hardware.generation.load(Ordering::Acquire),
+            })
+            .ok_or_else(|| HidError::HidApiError {
+                message: format!("No simulated device at {:?}", interface.path),
+            })
+    }
+}
+
+struct SimulatedHardware {
+    product_id: u16,
+    product_string: String,
+    serial_number: String,
+    path_prefix: String,
+    input_sender: Sender<Vec<u8>>,
+    input_receiver: Receiver<Vec<u8>>,
+    feature_reports: Mutex<Vec<Vec<u8>>>,
+    feature_reports_changed: Condvar,
+    /// Odd while unplugged, bumped on every plug change so stale transports start failing.
+    generation: AtomicU32,
+    single_led_reports_only: AtomicBool,
+    /// Feature reports still to be refused.
+    refused_led_reports: AtomicUsize,
+    led_report_delay: Mutex<Duration>,
+}
+impl SimulatedHardware {
+    fn is_plugged(&self) -> bool {
+        self.generation.load(Ordering::Acquire) & 1 == 0
+    }
+
+    /// Whether `report` sets more than one LED, either through several slots or a [`BoardType::Default`] broadcast.
+    fn sets_several_leds(report: &[u8]) -> bool {
+        report.len() == 38
+            && (report[1] == BoardType::Default as u8
+                || report[4..37]
+                    .iter()
+                    .filter(|byte| color_from_byte(**byte).is_some())
+                    .count()
+                    > 1)
+    }
+}
+
+/// One opened interface of a simulated device.
+pub struct SimulatedTransport {
+    hardware: Arc<SimulatedHardware>,
+    generation: u32,
+}
+impl SimulatedTransport {
+    fn check_connected(&self) -> HidResult<()> {
+        if self.hardware.generation.load(Ordering::Acquire) == self.generation {
+            Ok(())
+        } else {
+            Err(HidError::HidApiError {
+                message: "Simulated device unplugged".to_owned(),
+            })
+        }
+    }
+}
+impl HidTransport for SimulatedTransport {
+    fn read(&self, buffer: &mut [u8]) -> HidResult<usize> {
+        self.check_connected()?;
+        match self
+            .hardware
+            .input_receiver
+            .recv_timeout(SIMULATED_READ_TIMEOUT)
+        {
+            Ok(report) => {
+                let count = report.len().min(buffer.len());
+                buffer[..count].copy_from_slice(&report[..count]);
+                Ok(count)
+            }
+            Err(RecvTimeoutError::Timeout) => Ok(0),
+            Err(RecvTimeoutError::Disconnected) => Err(HidError::HidApiErrorEmpty),
+        }
+    }
+
+    fn send_feature_report(&self, data: &[u8]) -> HidResult<()> {
+        self.check_connected()?;
+        std::thread::sleep(*self.hardware.led_report_delay.lock().unwrap());
+        if self
+            .hardware
+            .single_led_reports_only
+            .load(Ordering::Relaxed)
+            && SimulatedHardware::sets_several_leds(data)
+        {
+            return Err(HidError::HidApiError {
+                message: "Simulated firmware only takes one led per report".to_owned(),
+            });
+        }
+        if self
+            .hardware
+            .refused_led_reports
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                count.checked_sub(1)
+            })
+            .is_ok()
+        {
+            return Err(HidError::HidApiError {
+                message: "Simulated device refused the report".to_owned(),
+            });
+        }
+        self.hardware
+            .feature_reports
+            .lock()
+            .unwrap()
+            .push(data.to_vec());
+        self.hardware.feature_reports_changed.notify_all();
+        Ok(())
+    }
+
+    fn get_product_string(&self) -> HidResult<Option<String>> {
+        Ok(Some(self.hardware.product_string.clone()))
+    }
+}
+
+/// Scripting handle for a device added with [`SimulatedBackend::add_device`].
+///
+/// Axis and button setters only change the pending report, call [`SimulatedDevice::send_report`] to deliver it.
+pub struct SimulatedDevice<D>
+where
+    D: VirpilDeviceDescription,
+{
+    hardware: Arc<SimulatedHardware>,
+    axis: Vec<u16>,
+    buttons: Vec<u8>,
+    _description: PhantomData<fn() -> D>,
+}
+impl<D> SimulatedDevice<D>
+where
+    D: VirpilDeviceDescription,
+{
+    pub fn serial_number(&self) -> &str {
+        &self.hardware.serial_number
+    }
+
+    /// Makes open transports fail and hides the device from enumeration until [`SimulatedDevice::plug`].
+    pub fn unplug(&self) {
+        if self.hardware.is_plugged() {
+            self.hardware.generation.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+
+    pub fn plug(&self) {
+        if !self.hardware.is_plugged() {
+            self.hardware.generation.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+
+    /// Makes the device refuse batched and broadcast LED reports, like firmware that only takes one LED per report.
+    pub fn set_single_led_reports_only(&self, single: bool) {
+        self.hardware
+            .single_led_reports_only
+            .store(single, Ordering::Relaxed);
+    }
+
+    /// Makes the device refuse the next `count` feature reports while staying plugged in.
+    pub fn refuse_led_reports(&self, count: usize) {
+        self.hardware
+            .refused_led_reports
+            .store(count, Ordering::Release);
+    }
+
+    /// Makes every feature report take `delay`, like a device that is slow to take LED reports.
+    pub fn set_led_report_delay(&self, delay: Duration) {
+        *self.hardware.led_report_delay.lock().unwrap() = delay;
+    }
+
+    pub fn set_axis(&mut self, axis: D::Axis, value: u16) -> &mut Self {
+        self.axis[axis.to_axis_index() as usize] = value;
+        self
+    }
+
+    pub fn set_button(&mut self, button: D::Buttons, pressed: bool) -> &mut Self {
+        let index = button.to_button_index();
+        let byte = &mut self.buttons[index as usize / 8];
+        if pressed {
+            *byte |= 1 << (index % 8);
+        } else {
+            *byte &= !(1 << (index % 8));
+        }
+        self
+    }
+
+    pub fn press(&mut self, button: D::Buttons) -> &mut Self {
+        self.set_button(button, true)
+    }
+
+    pub fn release(&mut self, button: D::Buttons) -> &mut Self {
+        self.set_button(button, false)
+    }
+
+    /// The input report for the current axis and button values, laid out as the device sends it.
+    pub fn report(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(1 + self.axis.len() * 2 + self.buttons.len());
+        out.push(SIMULATED_REPORT_ID);
+        for axis in &self.axis {
+            out.extend_from_slice(&axis.to_le_bytes());
+        }
+        out.extend_from_slice(&self.buttons);
+        out
+    }
+
+    pub fn send_report(&self) {
+        self.send_raw_report(self.report());
+    }
+
+    /// Queues an arbitrary input report, useful for malformed data.
+    pub fn send_raw_report(&self, report: Vec<u8>) {
+        self.hardware.input_sender.send(report).unwrap();
+    }
+
+    /// Every feature report sent to the device so far, in order.
+    pub fn led_reports(&self) -> Vec<Vec<u8>> {
+        self.hardware.feature_reports.lock().unwrap().clone()
+    }
+
+    pub fn clear_led_reports(&self) {
+        self.hardware.feature_reports.lock().unwrap().clear();
+    }
+
+    /// Blocks until at least `count` feature reports have been recorded, returning false on timeout.
+    pub fn wait_for_led_reports(&self, count: usize, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut reports = self.hardware.feature_reports.lock().unwrap();
+        while reports.len() < count {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            reports = self
+                .hardware
+                .feature_reports_changed
+                .wait_timeout(reports, deadline - now)
+                .unwrap()
+                .0;
+        }
+        true
+    }
+
+    /// The last color written to `led`, decoded from the recorded feature reports. Slots other reports left empty
+    /// are skipped, so batched reports decode the same as single ones, and [`BoardType::Default`] broadcasts count
+    /// for every LED.
+    pub fn led_color(&self, led: D::Led) -> Option<Color> {
+        let (board_type, led_number) = led.to_board_and_led_number();
+        self.hardware
+            .feature_reports
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .filter(|report| report.len() == 38)
+            .find_map(|report| match report[1] {
+                board if board == BoardType::Default as u8 => color_from_byte(report[4]),
+                board if board == board_type as u8 => {
+                    color_from_byte(report[led_number as usize + 4])
+                }
+                _ => None,
+            })
+    }
+}
+
+fn button_bytes<D>() -> usize
+where
+    D: VirpilDeviceDescription,
+{
+    D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize
+}
+
+/// Polls `condition` until it holds, returning false after a second.
+#[cfg(test)]
+pub(crate) fn wait_until(mut condition: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while !condition() {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+    use crate::left_panel::{LeftPanel, LeftPanelButtons, LeftPanelLed};
+    use crate::throttle::{Throttle, ThrottleAxis, ThrottleButtons, ThrottleLed};
+    use crate::virpil_device::find_device;
+    use crate::LedPower;
+
+    #[test]
+    fn buttons_reach_button_state() {
+        let backend = SimulatedBackend::new();
+        let mut simulated = backend.add_device::<LeftPanel>();
+        let device = find_device::<LeftPanel, _>(&backend, LedPower::OFF).unwrap();
+        simulated.press(LeftPanelButtons::B1).send_report();
+        assert!(wait_until(|| device.button_state(LeftPanelButtons::B1)));
+        assert!(!device.button_state(LeftPanelButtons::B2));
+        simulated.release(LeftPanelButtons::B1).send_report();
+        assert!(wait_until(|| !device.button_state(LeftPanelButtons::B1)));
+    }
+
+    #[test]
+    fn axes_reach_axis_state() {
+        let backend = SimulatedBackend::new();
+        let mut simulated = backend.add_device::<Throttle>();
+        let device = find_device::<Throttle, _>(&backend, LedPower::OFF).unwrap();
+        simulated
+            .set_axis(ThrottleAxis::Slider, 1234)
+            .press(ThrottleButtons::B3)
+            .send_report();
+        assert!(wait_until(
+            || device.axis_state(ThrottleAxis::Slider) == 1234
+        ));
+        assert!(device.button_state(ThrottleButtons::B3));
+        assert_eq!(device.axis_state(ThrottleAxis::Flaps), 0);
+    }
+
+    #[test]
+    fn led_traffic_decodes() {
+        let backend = SimulatedBackend::new();
+        let simulated = backend.add_device::<Throttle>();
+        let mut device = find_device::<Throttle, _>(&backend, LedPower::FULL_RED).unwrap();
+        let leds = ThrottleLed::iter().count();
+        assert!(simulated.wait_for_led_reports(leds, Duration::from_secs(1)));
+        assert_eq!(
+            simulated.led_color(ThrottleLed::B1),
+            Some(LedPower::FULL_RED)
+        );
+        device
+            .set_led(ThrottleLed::B1, LedPower::FULL_GREEN)
+            .unwrap();
+        assert!(simulated.wait_for_led_reports(leds + 1, Duration::from_secs(1)));
+        assert_eq!(
+            simulated.led_color(ThrottleLed::B1),
+            Some(LedPower::FULL_GREEN)
+        );
+        assert_eq!(
+            simulated.led_color(ThrottleLed::B2),
+            Some(LedPower::FULL_RED)
+        );
+    }
+
+    #[test]
+    fn unplugged_device_is_not_found() {
+        let backend = SimulatedBackend::new();
+        let simulated = backend.add_device::<LeftPanel>();
+        simulated.unplug();
+        assert!(find_device::<LeftPanel, _>(&backend, LedPower::OFF).is_err());
+        simulated.plug();
+        let mut device = find_device::<LeftPanel, _>(&backend, LedPower::OFF).unwrap();
+        device
+            .set_led(LeftPanelLed::B1, LedPower::FULL_BLUE)
+            .unwrap();
+        assert!(wait_until(
+            || simulated.led_color(LeftPanelLed::B1) == Some(LedPower::FULL_BLUE)
+        ));
+    }
+}
@@ -0,0 +1,205 @@
+use std::fmt::{Debug, Formatter};
+use std::time::Instant;
+
+use strum::{EnumCount, IntoEnumIterator};
+
+use crate::events::{DeviceEventKind, InputEventKind};
+use crate::virpil_device::{ToAxisIndex, ToButtonIndex, VirpilDeviceDescription};
+
+/// Every axis and button of a device as decoded from a single input report.
+pub struct Snapshot<D>
+where
+    D: VirpilDeviceDescription,
+    [(); D::Axis::COUNT]:,
+    [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
+{
+    /// 1-based count of decoded reports, `0` before the first.
+    pub sequence: u64,
+    /// When the report was read, or when the device was opened for sequence `0`.
+    pub time: Instant,
+    pub axis: [u16; D::Axis::COUNT],
+    pub buttons: [u8; D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize],
+    /// Every [`VirtualButton`](crate::virtual_button::VirtualButton) and whether it is pressed, in the order they
+    /// were added. Filled in by the reader after decoding, empty in snapshots straight from
+    /// [`Snapshot::from_report`].
+    pub virtual_buttons: Vec<(&'static str, bool)>,
+}
+impl<D> Snapshot<D>
+where
+    D: VirpilDeviceDescription,
+    [(); D::Axis::COUNT]:,
+    [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
+{
+    pub fn new(time: Instant) -> Self {
+        Self {
+            sequence: 0,
+            time,
+            axis: [0; D::Axis::COUNT],
+            buttons: [0; D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize],
+            virtual_buttons: Vec::new(),
+        }
+    }
+
+    /// A snapshot at `time` with exactly `pressed` held, for feeding the input trackers in tests.
+    #[cfg(test)]
+    pub(crate) fn with_buttons(time: Instant, pressed: &[D::Buttons]) -> Self {
+        let mut out = Self::new(time);
+        for button in pressed {
+            let index = button.to_button_index() as usize;
+            out.buttons[index / 8] |= 1 << (index % 8);
+        }
+        out
+    }
+
+    /// Decodes a report without its id byte, returning `None` on a length mismatch.
+    pub fn from_report(sequence: u64, time: Instant, mut data: &[u8]) -> Option<Self> {
+        if data.len()
+            != D::Axis::COUNT * 2 + D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize
+        {
+            return None;
+        }
+        let mut out = Self::new(time);
+        out.sequence = sequence;
+        for axis in out.axis.iter_mut() {
+            let (val, rest) = data.split_array_ref();
+            data = rest;
+            *axis = u16::from_le_bytes(*val);
+        }
+        out.buttons.copy_from_slice(data);
+        Some(out)
+    }
+
+    pub fn button(&self, button: D::Buttons) -> bool {
+        let index = button.to_button_index();
+        self.buttons[index as usize / 8] & (1 << (index % 8)) > 0
+    }
+
+    pub fn axis(&self, axis: D::Axis) -> u16 {
+        self.axis[axis.to_axis_index() as usize]
+    }
+
+    /// `None` if no virtual button with that name is defined.
+    pub fn virtual_button(&self, name: &str) -> Option<bool> {
+        self.virtual_buttons
+            .iter()
+            .find(|(button, _)| *button == name)
+            .map(|(_, pressed)| *pressed)
+    }
+
+    /// Changes needed to go from `self` to `newer`, axes first then buttons in declaration order. Virtual buttons are
+    /// left out, their events come from the reader.
+    pub fn diff(&self, newer: &Self) -> Vec<DeviceEventKind<D>> {
+        let mut out = Vec::new();
+        for axis in D::Axis::iter() {
+            if self.axis(axis) != newer.axis(axis) {
+                out.push(InputEventKind::AxisMoved(axis, newer.axis(axis)));
+            }
+        }
+        if self.buttons != newer.buttons {
+            for button in D::Buttons::iter() {
+                match (self.button(button), newer.button(button)) {
+                    (false, true) => out.push(InputEventKind::ButtonPressed(button)),
+                    (true, false) => out.push(InputEventKind::ButtonReleased(button)),
+                    _ => {}
+                }
+            }
+        }
+        out
+    }
+}
+impl<D> Clone for Snapshot<D>
+where
+    D: VirpilDeviceDescription,
+    [(); D::Axis::COUNT]:,
+    [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
+{
+    fn clone(&self) -> Self {
+        Self {
+            sequence: self.sequence,
+            time: self.time,
+            axis: self.axis,
+            buttons: self.buttons,
+            virtual_buttons: self.virtual_buttons.clone(),
+        }
+    }
+}
+impl<D> Debug for Snapshot<D>
+where
+    D: VirpilDeviceDescription,
+    [(); D::Axis::COUNT]:,
+    [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Snapshot")
+            .field("sequence", &self.sequence)
+            .field("time", &self.time)
+            .field("axis", &self.axis)
+            .field("buttons", &self.buttons)
+            .field("virtual_buttons", &self.virtual_buttons)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::throttle::{Throttle, ThrottleAxis, ThrottleButtons};
+    use crate::virpil_device::ToAxisIndex;
+
+    fn report(axis: &[(ThrottleAxis, u16)], buttons: &[ThrottleButtons]) -> Vec<u8> {
+        let mut values = [0u16; ThrottleAxis::COUNT];
+        for (axis, value) in axis {
+            values[axis.to_axis_index() as usize] = *value;
+        }
+        let mut out: Vec<u8> = values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        let mut bytes =
+            vec![0; ThrottleButtons::COUNT / 8 + (ThrottleButtons::COUNT % 8 > 0) as usize];
+        for button in buttons {
+            let index = button.to_button_index() as usize;
+            bytes[index / 8] |= 1 << (index % 8);
+        }
+        out.extend(bytes);
+        out
+    }
+
+    #[test]
+    fn decodes_axes_and_buttons() {
+        let data = report(&[(ThrottleAxis::Slider, 0x1234)], &[ThrottleButtons::B3]);
+        let snapshot = Snapshot::<Throttle>::from_report(1, Instant::now(), &data).unwrap();
+        assert_eq!(snapshot.sequence, 1);
+        assert_eq!(snapshot.axis(ThrottleAxis::Slider), 0x1234);
+        assert_eq!(snapshot.axis(ThrottleAxis::Flaps), 0);
+        assert!(snapshot.button(ThrottleButtons::B3));
+        assert!(!snapshot.button(ThrottleButtons::B4));
+    }
+
+    #[test]
+    fn rejects_wrong_length() {
+        let data = report(&[], &[]);
+        assert!(Snapshot::<Throttle>::from_report(1, Instant::now(), &data[1..]).is_none());
+    }
+
+    #[test]
+    fn diff_lists_axes_then_buttons() {
+        let before = Snapshot::<Throttle>::new(Instant::now());
+        let data = report(&[(ThrottleAxis::Flaps, 7)], &[ThrottleButtons::B3]);
+        let after = Snapshot::<Throttle>::from_report(1, Instant::now(), &data).unwrap();
+        assert_eq!(
+            before.diff(&after),
+            vec![
+                InputEventKind::AxisMoved(ThrottleAxis::Flaps, 7),
+                InputEventKind::ButtonPressed(ThrottleButtons::B3),
+            ]
+        );
+        assert_eq!(
+            after.diff(&before),
+            vec![
+                InputEventKind::AxisMoved(ThrottleAxis::Flaps, 0),
+                InputEventKind::ButtonReleased(ThrottleButtons::B3),
+            ]
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Window the report rate is averaged over.
+pub const REPORT_RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Point in time copy of a device's counters.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DeviceStatistics {
+    pub reports_received: u64,
+    /// Reports per second over the last full [`REPORT_RATE_WINDOW`].
+    pub report_rate: f32,
+    pub malformed_reports: u64,
+    pub read_errors: u64,
+    pub led_packets_sent: u64,
+    pub led_write_failures: u64,
+    /// LED colors replaced by a newer one before they were sent.
+    pub led_updates_coalesced: u64,
+    /// Batched LED reports the device refused while taking the same color in a single report, which turns
+    /// batching off.
+    pub led_batch_fallbacks: u64,
+    /// Like `led_batch_fallbacks`, for reports broadcasting one color to every LED.
+    pub led_broadcast_fallbacks: u64,
+    /// `None` until the first report arrives.
+    pub time_since_last_report: Option<Duration>,
+}
+
+/// Counters updated by the reader and writer threads.
+#[derive(Debug)]
+pub struct StatisticsCounters {
+    reports_received: AtomicU64,
+    malformed_reports: AtomicU64,
+    read_errors: AtomicU64,
+    led_packets_sent: AtomicU64,
+    led_write_failures: AtomicU64,
+    led_updates_coalesced: AtomicU64,
+    led_batch_fallbacks: AtomicU64,
+    led_broadcast_fallbacks: AtomicU64,
+    timing: Mutex<ReportTiming>,
+}
+#[derive(Debug)]
+struct ReportTiming {
+    last_report: Option<Instant>,
+    window_start: Instant,
+    window_reports: u64,
+    rate: f32,
+}
+impl Default for StatisticsCounters {
+    fn default() -> Self {
+        Self {
+            reports_received: AtomicU64::new(0),
+            malformed_reports: AtomicU64::new(0),
+            read_errors: AtomicU64::new(0),
+            led_packets_sent: AtomicU64::new(0),
+            led_write_failures: AtomicU64::new(0),
+            led_updates_coalesced: AtomicU64::new(0),
+            led_batch_fallbacks: AtomicU64::new(0),
+            led_broadcast_fallbacks: AtomicU64::new(0),
+            timing: Mutex::new(ReportTiming {
+                last_report: None,
+                window_start: Instant::now(),
+                window_reports: 0,
+                rate: 0.0,
+            }),
+        }
+    }
+}
+impl StatisticsCounters {
+    pub(crate) fn report_received(&self, time: Instant) {
+        self.reports_received.fetch_add(1, Ordering::Relaxed);
+        let mut timing = self.timing.lock().unwrap();
+        timing.last_report = Some(time);
+        timing.window_reports += 1;
+        let window = time.saturating_duration_since(timing.window_start);
+        if window >= REPORT_RATE_WINDOW {
+            timing.rate = timing.window_reports as f32 / window.as_secs_f32();
+            timing.window_start = time;
+            timing.window_reports = 0;
+        }
+    }
+
+    pub(crate) fn malformed_report(&self) {
+        self.malformed_reports.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn read_error(&self) {
+        self.read_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn led_packet_sent(&self) {
+        self.led_packets_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn led_write_failure(&self) {
+        self.led_write_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn led_update_coalesced(&self) {
+        self.led_updates_coalesced.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn led_batch_fallback(&self) {
+        self.led_batch_fallbacks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn led_broadcast_fallback(&self) {
+        self.led_broadcast_fallbacks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> DeviceStatistics {
+        let now = Instant::now();
+        let timing = self.timing.lock().unwrap();
+        // Reports stop ending windows once a device goes quiet, so decay the rate instead of keeping it.
+        let window = now.saturating_duration_since(timing.window_start);
+        let report_rate = if window >= REPORT_RATE_WINDOW {
+            timing.window_reports as f32 / window.as_secs_f32()
+        } else {
+            timing.rate
+        };
+        DeviceStatistics {
+            reports_received: self.reports_received.load(Ordering::Relaxed),
+            report_rate,
+            malformed_reports: self.malformed_reports.load(Ordering::Relaxed),
+            read_errors: self.read_errors.load(Ordering::Relaxed),
+            led_packets_sent: self.led_packets_sent.load(Ordering::Relaxed),
+            led_write_failures: self.led_write_failures.load(Ordering::Relaxed),
+            led_updates_coalesced: self.led_updates_coalesced.load(Ordering::Relaxed),
+            led_batch_fallbacks: self.led_batch_fallbacks.load(Ordering::Relaxed),
+            led_broadcast_fallbacks: self.led_broadcast_fallbacks.load(Ordering::Relaxed),
+            time_since_last_report: timing
+                .last_report
+                .map(|last_report| now.saturating_duration_since(last_report)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::left_panel::LeftPanel;
+    use crate::simulator::{wait_until, SimulatedBackend};
+    use crate::virpil_device::find_device;
+    use crate::LedPower;
+
+    #[test]
+    fn report_rate_is_averaged_over_a_full_window() {
+        let counters = StatisticsCounters::default();
+        let start = Instant::now();
+        assert_eq!(counters.get().time_since_last_report, None);
+        // Reports every 50ms, the window ends with the 21st one, 1s in.
+        for report in 0..=20 {
+            counters.report_received(start + Duration::from_millis(50) * report);
+            if report < 20 {
+                assert_eq!(counters.get().report_rate, 0.0);
+            }
+        }
+        let statistics = counters.get();
+        assert_eq!(statistics.reports_received, 21);
+        assert!((20.0..=21.0).contains(&statistics.report_rate));
+        assert_eq!(statistics.time_since_last_report, Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn device_counts_reports_and_led_packets() {
+        let backend = SimulatedBackend::new();
+        let simulated = backend.add_device::<LeftPanel>();
+        let device = find_device::<LeftPanel, _>(&backend, LedPower::OFF).unwrap();
+        simulated.send_report();
+        simulated.send_raw_report(vec![0x02, 0x00]);
+        assert!(wait_until(|| {
+            let statistics = device.statistics();
+            statistics.reports_received == 1 && statistics.malformed_reports == 1
+        }));
+        let statistics = device.statistics();
+        assert_eq!(
+            statistics.led_packets_sent,
+            simulated.led_reports().len() as u64
+        );
+        assert_eq!(statistics.read_errors, 0);
+        assert!(statistics.time_since_last_report.is_some());
+    }
+}
@@ -1,6 +1,11 @@
 use strum::{EnumCount, EnumIter};
 
-use crate::virpil_device::{ToAxisIndex, ToButtonIndex};
+use crate::axis::AxisInfo;
+use crate::hat::HatButtons;
+use crate::virpil_device::{
+    ToAxisIndex, ToAxisInfo, ToButtonIndex, ToEncoderButtons, ToGuardedSwitchButtons, ToHatButtons,
+    ToSelectorPositions,
+};
 use crate::{BoardType, ToBoardAndLedNumber, VirpilDeviceDescription};
 
 pub struct Throttle;
@@ -8,6 +13,11 @@ impl VirpilDeviceDescription for Throttle {
     type Led = ThrottleLed;
     type Buttons = ThrottleButtons;
     type Axis = ThrottleAxis;
+    type Encoder = ThrottleEncoder;
+    type Hat = ThrottleHat;
+    type Selector = ThrottleSelector;
+    type SelectorPosition = ThrottleSelectorPosition;
+    type GuardedSwitch = ThrottleGuardedSwitch;
     const PID: u16 = 0x0194;
 }
 
@@ -130,3 +140,175 @@ impl ToAxisIndex for ThrottleAxis {
         *self as u8 - 1
     }
 }
+impl ToAxisInfo for ThrottleAxis {
+    fn to_axis_info(&self) -> AxisInfo {
+        match self {
+            ThrottleAxis::StickX | ThrottleAxis::StickY => AxisInfo::CENTERED,
+            _ => AxisInfo::UNIPOLAR,
+        }
+    }
+}
+
+#[derive(EnumCount, EnumIter, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ThrottleEncoder {
+    E1,
+    E2,
+}
+impl ToEncoderButtons<ThrottleButtons> for ThrottleEncoder {
+    fn to_encoder_buttons(&self) -> (ThrottleButtons, ThrottleButtons) {
+        match self {
+            ThrottleEncoder::E1 => (
+                ThrottleButtons::E1Clockwise,
+                ThrottleButtons::E1CounterClockwise,
+            ),
+            ThrottleEncoder::E2 => (
+                ThrottleButtons::E2Clockwise,
+                ThrottleButtons::E2CounterClockwise,
+            ),
+        }
+    }
+}
+
+/// `Forward` and `Backward` are reported as right and left.
+#[derive(EnumCount, EnumIter, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ThrottleHat {
+    IndexHat,
+    WheelHat,
+    UpperHat,
+    LowerHat,
+}
+impl ToHatButtons<ThrottleButtons> for ThrottleHat {
+    fn to_hat_buttons(&self) -> HatButtons<ThrottleButtons> {
+        match self {
+            ThrottleHat::IndexHat => HatButtons {
+                up: ThrottleButtons::IndexHatUp,
+                right: ThrottleButtons::IndexHatRight,
+                down: ThrottleButtons::IndexHatDown,
+                left: ThrottleButtons::IndexHatLeft,
+                press: ThrottleButtons::IndexHatPress,
+            },
+            ThrottleHat::WheelHat => HatButtons {
+                up: ThrottleButtons::WheelHatUp,
+                right: ThrottleButtons::WheelHatForward,
+                down: ThrottleButtons::WheelHatDown,
+                left: ThrottleButtons::WheelHatBackward,
+                press: ThrottleButtons::WheelHatPress,
+            },
+            ThrottleHat::UpperHat => HatButtons {
+                up: ThrottleButtons::UpperHatUp,
+                right: ThrottleButtons::UpperHatForward,
+                down: ThrottleButtons::UpperHatDown,
+                left: ThrottleButtons::UpperHatBackward,
+                press: ThrottleButtons::UpperHatPress,
+            },
+            ThrottleHat::LowerHat => HatButtons {
+                up: ThrottleButtons::LowerHatUp,
+                right: ThrottleButtons::LowerHatForward,
+                down: ThrottleButtons::LowerHatDown,
+                left: ThrottleButtons::LowerHatBackward,
+                press: ThrottleButtons::LowerHatPress,
+            },
+        }
+    }
+}
+
+#[derive(EnumCount, EnumIter, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ThrottleSelector {
+    Mode,
+    Flaps,
+    Slider,
+}
+impl ToSelectorPositions<ThrottleButtons, ThrottleSelectorPosition> for ThrottleSelector {
+    fn to_selector_positions(&self) -> &'static [(ThrottleSelectorPosition, ThrottleButtons)] {
+        match self {
+            ThrottleSelector::Mode => &[
+                (
+                    ThrottleSelectorPosition::Mode(ThrottleMode::M1),
+                    ThrottleButtons::Mode1,
+                ),
+                (
+                    ThrottleSelectorPosition::Mode(ThrottleMode::M2),
+                    ThrottleButtons::Mode2,
+                ),
+                (
+                    ThrottleSelectorPosition::Mode(ThrottleMode::M3),
+                    ThrottleButtons::Mode3,
+                ),
+                (
+                    ThrottleSelectorPosition::Mode(ThrottleMode::M4),
+                    ThrottleButtons::Mode4,
+                ),
+                (
+                    ThrottleSelectorPosition::Mode(ThrottleMode::M5),
+                    ThrottleButtons::Mode5,
+                ),
+            ],
+            ThrottleSelector::Flaps => &[
+                (
+                    ThrottleSelectorPosition::Flaps(ThrottleFlaps::Down),
+                    ThrottleButtons::FlapsDown,
+                ),
+                (
+                    ThrottleSelectorPosition::Flaps(ThrottleFlaps::Middle),
+                    ThrottleButtons::FlapsMiddle,
+                ),
+                (
+                    ThrottleSelectorPosition::Flaps(ThrottleFlaps::Up),
+                    ThrottleButtons::FlapsUp,
+                ),
+            ],
+            ThrottleSelector::Slider => &[
+                (
+                    ThrottleSelectorPosition::Slider(ThrottleSlider::Down),
+                    ThrottleButtons::SliderDown,
+                ),
+                (
+                    ThrottleSelectorPosition::Slider(ThrottleSlider::Middle),
+                    ThrottleButtons::SliderMiddle,
+                ),
+                (
+                    ThrottleSelectorPosition::Slider(ThrottleSlider::Up),
+                    ThrottleButtons::SliderUp,
+                ),
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ThrottleSelectorPosition {
+    Mode(ThrottleMode),
+    Flaps(ThrottleFlaps),
+    Slider(ThrottleSlider),
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ThrottleMode {
+    M1,
+    M2,
+    M3,
+    M4,
+    M5,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ThrottleFlaps {
+    Down,
+    Middle,
+    Up,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ThrottleSlider {
+    Down,
+    Middle,
+    Up,
+}
+
+#[derive(EnumCount, EnumIter, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ThrottleGuardedSwitch {}
+impl ToGuardedSwitchButtons<ThrottleButtons> for ThrottleGuardedSwitch {
+    fn to_guarded_switch_buttons(&self) -> (ThrottleButtons, ThrottleButtons) {
+        unreachable!()
+    }
+}
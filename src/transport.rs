@@ -0,0 +1,131 @@
+use std::ffi::CString;
+use std::time::Duration;
+
+use hidapi::{HidApi, HidDevice, HidResult};
+
+/// How long [`HidTransport::read`] waits for a report on real hardware before returning an empty read.
+pub const READ_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// A single opened HID interface that a [`VirpilDevice`](crate::virpil_device::VirpilDevice) talks through.
+pub trait HidTransport: Send + 'static {
+    /// Reads one input report into `buffer`, returning the number of bytes read or `0` if none arrived in a short
+    /// while so the reader can handle timers.
+    fn read(&self, buffer: &mut [u8]) -> HidResult<usize>;
+    /// Sends a feature report, the first byte being the report id.
+    fn send_feature_report(&self, data: &[u8]) -> HidResult<()>;
+    fn get_product_string(&self) -> HidResult<Option<String>>;
+}
+impl HidTransport for HidDevice {
+    fn read(&self, buffer: &mut [u8]) -> HidResult<usize> {
+        HidDevice::read_timeout(self, buffer, READ_TIMEOUT.as_millis() as i32)
+    }
+
+    fn send_feature_report(&self, data: &[u8]) -> HidResult<()> {
+        HidDevice::send_feature_report(self, data)
+    }
+
+    fn get_product_string(&self) -> HidResult<Option<String>> {
+        HidDevice::get_product_string(self)
+    }
+}
+
+/// Enumerates and opens interfaces for a [`HidTransport`].
+pub trait HidBackend {
+    type Device: HidTransport;
+
+    /// Re-enumerates attached devices so [`HidBackend::interfaces`] picks up replugged ones.
+    fn refresh(&mut self) -> HidResult<()> {
+        Ok(())
+    }
+    fn interfaces(&self) -> Vec<InterfaceInfo>;
+    fn open(&self, interface: &InterfaceInfo) -> HidResult<Self::Device>;
+}
+impl HidBackend for HidApi {
+    type Device = HidDevice;
+
+    fn refresh(&mut self) -> HidResult<()> {
+        self.refresh_devices()
+    }
+
+    fn interfaces(&self) -> Vec<InterfaceInfo> {
+        self.device_list()
+            .map(|device| InterfaceInfo {
+                path: device.path().to_owned(),
+                vendor_id: device.vendor_id(),
+                product_id: device.product_id(),
+                usage: device.usage(),
+                serial_number: device.serial_number().map(str::to_owned),
+                product_string: device.product_string().map(str::to_owned),
+            })
+            .collect()
+    }
+
+    fn open(&self, interface: &InterfaceInfo) -> HidResult<Self::Device> {
+        self.open_path(&interface.path)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct InterfaceInfo {
+    pub path: CString,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub usage: u16,
+    pub serial_number: Option<String>,
+    pub product_string: Option<String>,
+}
+impl InterfaceInfo {
+    /// The part of the path shared by every interface of one physical device.
+    ///
+    /// Only libusb paths of the form `bus:device:interface` (hex numbers) have one, `bus:device`. hidraw nodes,
+    /// macOS `IOService:` paths and Windows device paths return `None`.
+    pub fn path_prefix(&self) -> Option<String> {
+        let path = self.path.to_string_lossy();
+        let parts: Vec<_> = path.split(':').collect();
+        let is_hex =
+            |part: &str| !part.is_empty() && part.chars().all(|char| char.is_ascii_hexdigit());
+        (parts.len() == 3 && parts.iter().all(|part| is_hex(part)))
+            .then(|| format!("{}:{}", parts[0], parts[1]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interface(path: &str) -> InterfaceInfo {
+        InterfaceInfo {
+            path: CString::new(path).unwrap(),
+            vendor_id: 0x3344,
+            product_id: 0x8194,
+            usage: 4,
+            serial_number: None,
+            product_string: None,
+        }
+    }
+
+    #[test]
+    fn libusb_paths_share_bus_and_device() {
+        assert_eq!(
+            interface("0001:0005:00").path_prefix(),
+            Some("0001:0005".to_owned())
+        );
+        assert_eq!(
+            interface("0001:0005:01").path_prefix(),
+            interface("0001:0005:00").path_prefix()
+        );
+    }
+
+    #[test]
+    fn other_paths_have_no_prefix() {
+        for path in [
+            "/dev/hidraw3",
+            "IOService:/AppleACPIPlatformExpert/PCI0@0/AppleACPIPCI/XHC1@14/XHC1@14000000/HS02@14200000/VPC Throttle@14200000/IOUSBHostInterface@0/AppleUserUSBHostHIDDevice",
+            "\\\\?\\hid#vid_3344&pid_8194&mi_00#7&2b8a1f45&0&0000#{4d1e55b2-f16f-11cf-88cb-001111000030}",
+            "0001:0005",
+            "0001:zz05:00",
+        ] {
+            assert_eq!(interface(path).path_prefix(), None, "{}", path);
+        }
+    }
+}
@@ -1,27 +1,104 @@
 use core::cmp::Eq;
+use core::fmt::Debug;
 use core::hash::Hash;
 use core::result::Result::Ok;
 use std::collections::HashMap;
+use std::marker::PhantomData;
 use std::mem::ManuallyDrop;
-use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU8, Ordering};
-use std::sync::Arc;
-use std::thread::{spawn, JoinHandle};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{sleep, spawn, JoinHandle};
+use std::time::{Duration, Instant};
 
-use array_init::array_init;
-use crossbeam::channel::{unbounded, Receiver, Sender};
-use hidapi::{HidApi, HidDevice, HidResult};
+use crossbeam::channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use hidapi::{HidDevice, HidResult};
 use strum::{EnumCount, EnumIter, IntoEnumIterator};
 
-use crate::{send_command, BoardType, Color, LedPower, ToBoardAndLedNumber};
+use crate::animation::{Animations, Effect, ANIMATION_TICK};
+use crate::axis::{AxisConfig, AxisInfo, AxisPipeline};
+use crate::encoder::{EncoderAcceleration, Encoders};
+use crate::error::{VirpilError, VirpilResult};
+use crate::events::{DeviceEvent, EventPublisher, InputEvent, InputEventKind};
+use crate::gesture::{GestureConfig, Gestures};
+use crate::guarded_switch::{GuardPolicy, GuardedSwitchState, GuardedSwitches};
+use crate::hat::{DiagonalHandling, HatButtons, HatState, Hats};
+use crate::layer::{ShiftLayers, ShiftModifier, BASE_LAYER};
+use crate::led_binding::{LedBinding, LedBindings};
+use crate::recording::{PacketKind, Recorder};
+use crate::selector::Selectors;
+use crate::snapshot::Snapshot;
+use crate::statistics::{DeviceStatistics, StatisticsCounters};
+use crate::transport::{HidBackend, HidTransport, InterfaceInfo};
+use crate::virtual_button::{VirtualButton, VirtualButtons};
+use crate::{
+    packet_for_command, packet_for_leds, send_command, BoardType, Color, LedPower,
+    ToBoardAndLedNumber,
+};
 
 pub const VIRPIL_VID: u16 = 0x3344;
 
 pub const MAX_AXIS_VALUE: u16 = u16::from_le_bytes([0, 64]);
 
+/// How often a disconnected device is looked for again.
+pub const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Shortest wait before an LED the device refused is written again.
+const LED_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
 pub trait VirpilDeviceDescription {
     type Led: ToBoardAndLedNumber + IntoEnumIterator + EnumCount + Eq + Hash + Send + Copy;
-    type Buttons: ToButtonIndex + IntoEnumIterator + EnumCount + Eq + Hash + Copy;
-    type Axis: ToAxisIndex + IntoEnumIterator + EnumCount + Eq + Hash + Copy;
+    type Buttons: ToButtonIndex
+        + IntoEnumIterator
+        + EnumCount
+        + Eq
+        + Hash
+        + Send
+        + Copy
+        + Debug
+        + 'static;
+    type Axis: ToAxisIndex
+        + ToAxisInfo
+        + IntoEnumIterator
+        + EnumCount
+        + Eq
+        + Hash
+        + Send
+        + Copy
+        + Debug;
+    type Encoder: ToEncoderButtons<Self::Buttons>
+        + IntoEnumIterator
+        + EnumCount
+        + Eq
+        + Hash
+        + Send
+        + Copy
+        + Debug;
+    type Hat: ToHatButtons<Self::Buttons>
+        + IntoEnumIterator
+        + EnumCount
+        + Eq
+        + Hash
+        + Send
+        + Copy
+        + Debug;
+    type Selector: ToSelectorPositions<Self::Buttons, Self::SelectorPosition>
+        + IntoEnumIterator
+        + EnumCount
+        + Eq
+        + Hash
+        + Send
+        + Copy
+        + Debug;
+    /// Position of any selector, usually one variant per selector wrapping that selector's own position enum.
+    type SelectorPosition: Eq + Hash + Send + Copy + Debug + 'static;
+    type GuardedSwitch: ToGuardedSwitchButtons<Self::Buttons>
+        + IntoEnumIterator
+        + EnumCount
+        + Eq
+        + Hash
+        + Send
+        + Copy
+        + Debug;
 
     const PID: u16;
 }
@@ -31,85 +108,771 @@ pub trait ToButtonIndex {
 pub trait ToAxisIndex {
     fn to_axis_index(&self) -> u8;
 }
+pub trait ToAxisInfo {
+    fn to_axis_info(&self) -> AxisInfo;
+}
+pub trait ToEncoderButtons<B> {
+    /// The `(clockwise, counter_clockwise)` buttons the encoder pulses.
+    fn to_encoder_buttons(&self) -> (B, B);
+}
+pub trait ToHatButtons<B> {
+    fn to_hat_buttons(&self) -> HatButtons<B>;
+}
+pub trait ToSelectorPositions<B: 'static, P: 'static> {
+    /// Every position and the button reporting it, in physical order.
+    fn to_selector_positions(&self) -> &'static [(P, B)];
+}
+pub trait ToGuardedSwitchButtons<B> {
+    /// The `(guard, switch)` buttons.
+    fn to_guarded_switch_buttons(&self) -> (B, B);
+}
+
+/// Opens the first `D`. The device can't be reopened after being unplugged and goes [`DeviceStatus::Lost`], use
+/// [`find_reconnecting_device`] to keep it across replugs.
+pub fn find_device<D, B>(hid: &B, starting_color: Color) -> VirpilResult<VirpilDevice<D, B::Device>>
+where
+    D: VirpilDeviceDescription + 'static,
+    B: HidBackend,
+    [(); D::Axis::COUNT]:,
+    [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
+{
+    find_device_by(hid, &DeviceSelector::Any, starting_color)
+}
+
+/// Opens the first `D` matching `selector`.
+pub fn find_device_by<D, B>(
+    hid: &B,
+    selector: &DeviceSelector,
+    starting_color: Color,
+) -> VirpilResult<VirpilDevice<D, B::Device>>
+where
+    D: VirpilDeviceDescription + 'static,
+    B: HidBackend,
+    [(); D::Axis::COUNT]:,
+    [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
+{
+    let (device, state_read, led_write) = open_interfaces::<D, B>(hid, selector)?;
+    VirpilDevice::create(
+        state_read,
+        led_write,
+        starting_color,
+        device.selector(),
+        None,
+    )
+}
+
+/// Opens every connected `D`.
+pub fn find_devices<D, B>(
+    hid: &B,
+    starting_color: Color,
+) -> VirpilResult<Vec<VirpilDevice<D, B::Device>>>
+where
+    D: VirpilDeviceDescription + 'static,
+    B: HidBackend,
+    [(); D::Axis::COUNT]:,
+    [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
+{
+    physical_devices(hid)
+        .into_iter()
+        .filter(|device| device.product_id == D::PID)
+        .map(|device| {
+            let (state_read, led_write) = device.open(hid)?;
+            VirpilDevice::create(
+                state_read,
+                led_write,
+                starting_color,
+                device.selector(),
+                None,
+            )
+        })
+        .collect()
+}
+
+/// Like [`find_device`] but keeps a handle to `hid` so the device is reopened after being unplugged.
+pub fn find_reconnecting_device<D, B>(
+    hid: &Arc<Mutex<B>>,
+    starting_color: Color,
+) -> VirpilResult<VirpilDevice<D, B::Device>>
+where
+    D: VirpilDeviceDescription + 'static,
+    B: HidBackend + Send + 'static,
+    [(); D::Axis::COUNT]:,
+    [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
+{
+    find_reconnecting_device_by(hid, &DeviceSelector::Any, starting_color)
+}
 
-pub fn find_device<D>(hid: &HidApi, starting_color: Color) -> HidResult<VirpilDevice<D>>
+/// Like [`find_device_by`] but reopens the same physical device after it is unplugged.
+pub fn find_reconnecting_device_by<D, B>(
+    hid: &Arc<Mutex<B>>,
+    selector: &DeviceSelector,
+    starting_color: Color,
+) -> VirpilResult<VirpilDevice<D, B::Device>>
 where
     D: VirpilDeviceDescription + 'static,
+    B: HidBackend + Send + 'static,
     [(); D::Axis::COUNT]:,
     [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
 {
-    let mut led_write = None;
-    let mut state_read = None;
-    for device in hid.device_list() {
-        if device.vendor_id() == VIRPIL_VID && device.product_id() == D::PID {
-            match device.usage() {
-                4 => assert!(state_read.replace(device.open_device(hid)?).is_none()),
-                1 => assert!(led_write.replace(device.open_device(hid)?).is_none()),
-                x => panic!("Unknown usage {}", x),
+    let (device, state_read, led_write) = open_interfaces::<D, B>(&*hid.lock().unwrap(), selector)?;
+    let selector = device.selector();
+    let reconnect_selector = selector.clone();
+    let hid = hid.clone();
+    VirpilDevice::create(
+        state_read,
+        led_write,
+        starting_color,
+        selector,
+        Some(Box::new(move || {
+            let mut hid = hid.lock().unwrap();
+            hid.refresh()?;
+            open_interfaces::<D, B>(&*hid, &reconnect_selector)
+                .map(|(_, state_read, led_write)| (state_read, led_write))
+        })),
+    )
+}
+
+fn open_interfaces<D, B>(
+    hid: &B,
+    selector: &DeviceSelector,
+) -> VirpilResult<(PhysicalDevice, B::Device, B::Device)>
+where
+    D: VirpilDeviceDescription,
+    B: HidBackend,
+{
+    let device = physical_devices(hid)
+        .into_iter()
+        .find(|device| device.product_id == D::PID && selector.matches(device))
+        .ok_or_else(|| VirpilError::DeviceNotFound {
+            product_id: D::PID,
+            selector: selector.clone(),
+        })?;
+    let (state_read, led_write) = device.open(hid)?;
+    Ok((device, state_read, led_write))
+}
+
+/// Picks one physical device when several with the same PID are connected.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum DeviceSelector {
+    Any,
+    SerialNumber(String),
+    /// Either the full path of one of the device's interfaces or their shared [`InterfaceInfo::path_prefix`].
+    Path(String),
+}
+impl DeviceSelector {
+    pub fn matches(&self, device: &PhysicalDevice) -> bool {
+        match self {
+            DeviceSelector::Any => true,
+            DeviceSelector::SerialNumber(serial_number) => {
+                device.serial_number.as_ref() == Some(serial_number)
+            }
+            DeviceSelector::Path(path) => {
+                &device.path_prefix == path
+                    || device
+                        .interfaces
+                        .iter()
+                        .any(|interface| interface.path.to_string_lossy() == path.as_str())
+            }
+        }
+    }
+}
+
+/// All interfaces belonging to one plugged in Virpil device.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PhysicalDevice {
+    pub product_id: u16,
+    pub serial_number: Option<String>,
+    pub path_prefix: String,
+    pub interfaces: Vec<InterfaceInfo>,
+}
+impl PhysicalDevice {
+    /// The most specific selector that will find this device again after a replug.
+    pub fn selector(&self) -> DeviceSelector {
+        match &self.serial_number {
+            Some(serial_number) => DeviceSelector::SerialNumber(serial_number.clone()),
+            None => DeviceSelector::Path(self.path_prefix.clone()),
+        }
+    }
+
+    /// Opens the state and LED interfaces, returning `(state_read, led_write)`.
+    pub fn open<B>(&self, hid: &B) -> VirpilResult<(B::Device, B::Device)>
+    where
+        B: HidBackend,
+    {
+        let mut led_write = None;
+        let mut state_read = None;
+        for interface in &self.interfaces {
+            let slot = match interface.usage {
+                4 => &mut state_read,
+                1 => &mut led_write,
+                usage => {
+                    return Err(VirpilError::UnexpectedUsage {
+                        path: interface.path.to_string_lossy().into_owned(),
+                        usage,
+                    })
+                }
+            };
+            if slot.replace(hid.open(interface)?).is_some() {
+                return Err(VirpilError::DuplicateInterface {
+                    path: self.path_prefix.clone(),
+                    usage: interface.usage,
+                });
+            }
+        }
+        let state_read = state_read.ok_or_else(|| VirpilError::InputInterfaceMissing {
+            path: self.path_prefix.clone(),
+        })?;
+        let led_write = led_write.ok_or_else(|| VirpilError::LedInterfaceMissing {
+            path: self.path_prefix.clone(),
+        })?;
+        Ok((state_read, led_write))
+    }
+}
+
+/// Groups every Virpil interface of `hid` by physical device.
+///
+/// Interfaces are grouped by serial number, or by [`InterfaceInfo::path_prefix`] for devices without one. An
+/// interface with neither is a device of its own.
+pub fn physical_devices<B>(hid: &B) -> Vec<PhysicalDevice>
+where
+    B: HidBackend,
+{
+    let mut out: Vec<PhysicalDevice> = Vec::new();
+    for interface in hid.interfaces() {
+        if interface.vendor_id != VIRPIL_VID {
+            continue;
+        }
+        let path_prefix = interface.path_prefix();
+        let serial_number = interface
+            .serial_number
+            .as_ref()
+            .filter(|serial_number| !serial_number.is_empty());
+        let existing = out.iter_mut().find(|device| {
+            device.product_id == interface.product_id
+                && match (serial_number, &path_prefix) {
+                    (Some(serial_number), _) => {
+                        device.serial_number.as_ref() == Some(serial_number)
+                    }
+                    (None, Some(path_prefix)) => {
+                        device.serial_number.is_none() && &device.path_prefix == path_prefix
+                    }
+                    (None, None) => false,
+                }
+        });
+        match existing {
+            Some(device) => device.interfaces.push(interface),
+            None => out.push(PhysicalDevice {
+                product_id: interface.product_id,
+                serial_number: serial_number.cloned(),
+                path_prefix: path_prefix
+                    .unwrap_or_else(|| interface.path.to_string_lossy().into_owned()),
+                interfaces: vec![interface],
+            }),
+        }
+    }
+    out
+}
+
+/// Reopens both interfaces of a device, returning `(state_read, led_write)`.
+pub type Reconnect<T> = Box<dyn FnMut() -> VirpilResult<(T, T)> + Send>;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum DeviceStatus {
+    Connected,
+    /// Unplugged, looked for again every [`RECONNECT_INTERVAL`].
+    Disconnected,
+    /// Unplugged without a way to reopen it, only devices from [`find_reconnecting_device`] or
+    /// [`VirpilDevice::with_reconnect`] come back. The device has to be dropped and opened again.
+    Lost,
+}
+
+enum WriteCommand<L, T> {
+    Led(L, Color),
+    /// Several base colors applied together, so LEDs of one board can share a report.
+    Leds(Vec<(L, Color)>),
+    /// The same base color for every LED, broadcast in one report where the firmware supports it.
+    AllLeds(Color),
+    Reconnected(T),
+    /// The active shift layer changed.
+    Layer(u8),
+    /// Replaces the colors shown while a layer is active.
+    LayerColors(u8, HashMap<L, Color>),
+    /// Color from a running animation, shown over everything else, `None` once it stopped.
+    Animated(L, Option<Color>),
+    /// Color from an LED binding, shown over the base color, `None` once unbound.
+    Bound(L, Option<Color>),
+}
+
+impl<L, T> WriteCommand<L, T>
+where
+    L: Copy,
+{
+    /// The single LED a command changes.
+    fn led(&self) -> Option<L> {
+        match self {
+            WriteCommand::Led(led, _)
+            | WriteCommand::Animated(led, _)
+            | WriteCommand::Bound(led, _) => Some(*led),
+            _ => None,
+        }
+    }
+}
+
+/// Every source of LED colors the writer knows of, from lowest to highest priority.
+struct LedColors<L> {
+    base: HashMap<L, Color>,
+    bound: HashMap<L, Color>,
+    layer: u8,
+    layer_colors: HashMap<u8, HashMap<L, Color>>,
+    animated: HashMap<L, Color>,
+}
+impl<L> LedColors<L>
+where
+    L: Eq + Hash + Copy,
+{
+    fn new(base: HashMap<L, Color>) -> Self {
+        Self {
+            base,
+            bound: HashMap::new(),
+            layer: BASE_LAYER,
+            layer_colors: HashMap::new(),
+            animated: HashMap::new(),
+        }
+    }
+
+    /// Once stopping only the base colors are shown, so the device is left in the colors set on drop.
+    fn shown(&self, led: L, stopping: bool) -> Color {
+        let base = self.base[&led];
+        if stopping {
+            return base;
+        }
+        self.animated
+            .get(&led)
+            .or_else(|| {
+                self.layer_colors
+                    .get(&self.layer)
+                    .and_then(|colors| colors.get(&led))
+            })
+            .or_else(|| self.bound.get(&led))
+            .copied()
+            .unwrap_or(base)
+    }
+
+    fn apply<T>(&mut self, command: WriteCommand<L, T>) {
+        match command {
+            WriteCommand::Led(led, color) => {
+                self.base.insert(led, color);
+            }
+            WriteCommand::Leds(leds) => self.base.extend(leds),
+            WriteCommand::AllLeds(color) => self.base.values_mut().for_each(|base| *base = color),
+            WriteCommand::Animated(led, Some(color)) => {
+                self.animated.insert(led, color);
+            }
+            WriteCommand::Animated(led, None) => {
+                self.animated.remove(&led);
+            }
+            WriteCommand::Bound(led, Some(color)) => {
+                self.bound.insert(led, color);
+            }
+            WriteCommand::Bound(led, None) => {
+                self.bound.remove(&led);
+            }
+            WriteCommand::Layer(layer) => self.layer = layer,
+            WriteCommand::LayerColors(layer, colors) => {
+                self.layer_colors.insert(layer, colors);
             }
+            WriteCommand::Reconnected(_) => {}
         }
     }
-    VirpilDevice::new(state_read.unwrap(), led_write.unwrap(), starting_color)
 }
 
-pub struct VirpilDevice<D>
+pub struct VirpilDevice<D, T = HidDevice>
 where
     D: VirpilDeviceDescription + 'static,
+    T: HidTransport,
     [(); D::Axis::COUNT]:,
     [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
 {
-    threads: Option<[JoinHandle<()>; 2]>,
+    threads: Option<[JoinHandle<()>; 3]>,
     state: Arc<State<D>>,
-    led_write: ManuallyDrop<Sender<(D::Led, Color)>>,
+    led_write: ManuallyDrop<Sender<WriteCommand<D::Led, T>>>,
     led_states: HashMap<D::Led, Color>,
+    _transport: PhantomData<fn() -> T>,
 }
-impl<D> VirpilDevice<D>
+impl<D, T> VirpilDevice<D, T>
 where
     D: VirpilDeviceDescription + 'static,
+    T: HidTransport,
     [(); D::Axis::COUNT]:,
     [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
 {
-    pub fn new(
-        state_read: HidDevice,
-        led_write: HidDevice,
+    /// The device goes [`DeviceStatus::Lost`] on the first read error.
+    pub fn new(state_read: T, led_write: T, starting_color: Color) -> VirpilResult<Self> {
+        Self::create(
+            state_read,
+            led_write,
+            starting_color,
+            DeviceSelector::Any,
+            None,
+        )
+    }
+
+    /// Creates a device that calls `reconnect` periodically while disconnected until it succeeds.
+    pub fn with_reconnect(
+        state_read: T,
+        led_write: T,
+        starting_color: Color,
+        reconnect: Reconnect<T>,
+    ) -> VirpilResult<Self> {
+        Self::create(
+            state_read,
+            led_write,
+            starting_color,
+            DeviceSelector::Any,
+            Some(reconnect),
+        )
+    }
+
+    fn create(
+        state_read: T,
+        led_write: T,
         starting_color: Color,
-    ) -> HidResult<Self> {
+        selector: DeviceSelector,
+        reconnect: Option<Reconnect<T>>,
+    ) -> VirpilResult<Self> {
+        let state = Arc::new(State {
+            selector,
+            ..State::default()
+        });
         let mut led_states = HashMap::with_capacity(D::Led::COUNT);
         for val in D::Led::iter() {
             let (board_type, led_number) = val.to_board_and_led_number();
             send_command(&led_write, board_type, led_number, starting_color)?;
+            state.statistics.led_packet_sent();
             led_states.insert(val, starting_color);
         }
-        let state = Arc::<State<D>>::default();
         let state_clone = state.clone();
+        let write_state = state.clone();
         let (sender, receiver) = unbounded();
+        let reconnect_sender = sender.clone();
+        let write_led_states = led_states.clone();
+        let animation_state = state.clone();
+        let animation_sender = sender.clone();
         Ok(Self {
             threads: Some([
-                spawn(move || Self::state_read_loop(state_clone, state_read)),
-                spawn(move || Self::led_write_loop(led_write, receiver)),
+                spawn(move || {
+                    Self::state_read_loop(state_clone, state_read, reconnect, reconnect_sender)
+                }),
+                spawn(move || {
+                    Self::led_write_loop(write_state, led_write, receiver, write_led_states)
+                }),
+                spawn(move || Self::animation_loop(animation_state, animation_sender)),
             ]),
             state,
             led_write: ManuallyDrop::new(sender),
             led_states,
+            _transport: PhantomData,
         })
     }
 
+    /// Selects this physical device among identical ones, [`DeviceSelector::Any`] for devices built with
+    /// [`VirpilDevice::new`]. Recordings tag packets with it.
+    pub fn selector(&self) -> &DeviceSelector {
+        &self.state.selector
+    }
+
+    pub fn status(&self) -> DeviceStatus {
+        if self.state.lost.load(Ordering::Acquire) {
+            DeviceStatus::Lost
+        } else if self.state.connected.load(Ordering::Acquire) {
+            DeviceStatus::Connected
+        } else {
+            DeviceStatus::Disconnected
+        }
+    }
+
     pub fn button_state(&self, button: D::Buttons) -> bool {
-        let index = button.to_button_index();
-        self.state.buttons[index as usize / 8].load(Ordering::SeqCst) & (1 << (index % 8)) > 0
+        self.state.snapshot.read().unwrap().button(button)
     }
 
     pub fn axis_state(&self, axis: D::Axis) -> u16 {
-        self.state.axis[axis.to_axis_index() as usize].load(Ordering::SeqCst)
+        self.state.snapshot.read().unwrap().axis(axis)
+    }
+
+    /// All axes and buttons as decoded from the latest report.
+    pub fn snapshot(&self) -> Snapshot<D> {
+        self.state.snapshot.read().unwrap().clone()
     }
 
     pub fn axis_percent(&self, axis: D::Axis) -> f32 {
         self.axis_state(axis) as f32 / MAX_AXIS_VALUE as f32
     }
 
-    pub fn set_led(&mut self, led: D::Led, color: Color) -> HidResult<Color> {
+    /// The axis after the configured [`AxisConfig`] processing, `0..=1`.
+    pub fn axis_processed(&self, axis: D::Axis) -> f32 {
+        self.state.axis_pipeline.lock().unwrap().output(axis)
+    }
+
+    /// The processed axis as `-1..=1` for [`AxisKind::Centered`](crate::axis::AxisKind::Centered) axes and
+    /// `0..=1` for everything else.
+    pub fn axis_normalized(&self, axis: D::Axis) -> f32 {
+        axis.to_axis_info().normalize(self.axis_processed(axis))
+    }
+
+    pub fn axis_config(&self, axis: D::Axis) -> AxisConfig {
+        self.state.axis_pipeline.lock().unwrap().config(axis)
+    }
+
+    /// Replaces the processing for `axis`, taking effect from the next report. Returns the old config.
+    pub fn set_axis_config(&self, axis: D::Axis, config: AxisConfig) -> AxisConfig {
+        self.state
+            .axis_pipeline
+            .lock()
+            .unwrap()
+            .set_config(axis, config)
+    }
+
+    /// Defines a button driven by an axis range, replacing any virtual button with the same name. A replaced button
+    /// that was pressed is released first, the new one starts released and is evaluated from the next report.
+    pub fn add_virtual_button(
+        &self,
+        button: VirtualButton<D::Axis>,
+    ) -> Option<VirtualButton<D::Axis>> {
+        self.change_virtual_buttons(|buttons| buttons.insert(button))
+    }
+
+    /// Removes a virtual button, publishing its release if it was pressed.
+    pub fn remove_virtual_button(&self, name: &str) -> Option<VirtualButton<D::Axis>> {
+        self.change_virtual_buttons(|buttons| buttons.remove(name))
+    }
+
+    /// Serialised with the reader through the snapshot lock so the snapshot and released events stay consistent.
+    fn change_virtual_buttons(
+        &self,
+        change: impl FnOnce(&mut VirtualButtons<D>) -> Option<(VirtualButton<D::Axis>, bool)>,
+    ) -> Option<VirtualButton<D::Axis>> {
+        let mut current = self.state.snapshot.write().unwrap();
+        let layers = self.state.layers.lock().unwrap();
+        let mut buttons = self.state.virtual_buttons.lock().unwrap();
+        let old = change(&mut buttons);
+        current.virtual_buttons = buttons.states();
+        let (old, pressed) = old?;
+        if pressed {
+            let kind = InputEventKind::VirtualButtonReleased(old.name);
+            let event = InputEvent {
+                time: Instant::now(),
+                layer: layers.event_layer(&kind),
+                kind,
+            };
+            self.state.events.publish(&[event]);
+        }
+        Some(old)
+    }
+
+    /// `None` if no virtual button with that name is defined.
+    pub fn virtual_button_state(&self, name: &str) -> Option<bool> {
+        self.state.virtual_buttons.lock().unwrap().state(name)
+    }
+
+    /// Every virtual button's name and state, in the order they were added.
+    pub fn virtual_button_states(&self) -> Vec<(&'static str, bool)> {
+        self.state.virtual_buttons.lock().unwrap().states()
+    }
+
+    /// Detents turned since the device was opened, clockwise being positive.
+    pub fn encoder_detents(&self, encoder: D::Encoder) -> i64 {
+        self.state.encoders.lock().unwrap().detents(encoder)
+    }
+
+    /// Steps turned since the last call for this encoder, with its acceleration applied.
+    pub fn encoder_delta(&self, encoder: D::Encoder) -> i64 {
+        self.state.encoders.lock().unwrap().take_delta(encoder)
+    }
+
+    pub fn encoder_acceleration(&self, encoder: D::Encoder) -> Option<EncoderAcceleration> {
+        self.state.encoders.lock().unwrap().acceleration(encoder)
+    }
+
+    pub fn set_encoder_acceleration(
+        &self,
+        encoder: D::Encoder,
+        acceleration: Option<EncoderAcceleration>,
+    ) -> Option<EncoderAcceleration> {
+        self.state
+            .encoders
+            .lock()
+            .unwrap()
+            .set_acceleration(encoder, acceleration)
+    }
+
+    /// Direction and press of a hat as of the latest report.
+    pub fn hat_state(&self, hat: D::Hat) -> HatState {
+        self.state.hats.lock().unwrap().state(hat)
+    }
+
+    pub fn hat_diagonal_handling(&self, hat: D::Hat) -> DiagonalHandling {
+        self.state.hats.lock().unwrap().diagonal_handling(hat)
+    }
+
+    pub fn set_hat_diagonal_handling(
+        &self,
+        hat: D::Hat,
+        handling: DiagonalHandling,
+    ) -> DiagonalHandling {
+        self.state
+            .hats
+            .lock()
+            .unwrap()
+            .set_diagonal_handling(hat, handling)
+    }
+
+    /// The selector's current position, `None` until the device reported one.
+    pub fn selector_position(&self, selector: D::Selector) -> Option<D::SelectorPosition> {
+        self.state.selectors.lock().unwrap().position(selector)
+    }
+
+    /// Index of the selector's current position, `0` being the first of its positions.
+    pub fn selector_position_index(&self, selector: D::Selector) -> Option<usize> {
+        self.state
+            .selectors
+            .lock()
+            .unwrap()
+            .position_index(selector)
+    }
+
+    pub fn guarded_switch_state(&self, switch: D::GuardedSwitch) -> GuardedSwitchState {
+        self.state.guarded_switches.lock().unwrap().state(switch)
+    }
+
+    pub fn guard_policy(&self, switch: D::GuardedSwitch) -> GuardPolicy {
+        self.state.guarded_switches.lock().unwrap().policy(switch)
+    }
+
+    /// Takes effect from the next report.
+    pub fn set_guard_policy(&self, switch: D::GuardedSwitch, policy: GuardPolicy) -> GuardPolicy {
+        self.state
+            .guarded_switches
+            .lock()
+            .unwrap()
+            .set_policy(switch, policy)
+    }
+
+    /// How long the button has been held, `None` while released.
+    pub fn button_held_for(&self, button: D::Buttons) -> Option<Duration> {
+        self.state
+            .gestures
+            .lock()
+            .unwrap()
+            .held_for(button, Instant::now())
+    }
+
+    pub fn gesture_config(&self, button: D::Buttons) -> Option<GestureConfig> {
+        self.state.gestures.lock().unwrap().config(button)
+    }
+
+    /// Starts sending [`Gesture`](crate::gesture::Gesture) events for `button`, or stops with `None`.
+    pub fn set_gesture_config(
+        &self,
+        button: D::Buttons,
+        config: Option<GestureConfig>,
+    ) -> Option<GestureConfig> {
+        self.state
+            .gestures
+            .lock()
+            .unwrap()
+            .set_config(button, config)
+    }
+
+    /// The active shift layer, [`BASE_LAYER`](crate::layer::BASE_LAYER) when nothing is shifted.
+    pub fn layer(&self) -> u8 {
+        self.state.layers.lock().unwrap().layer()
+    }
+
+    /// Layer a held button was pressed in, `None` while released.
+    pub fn button_layer(&self, button: D::Buttons) -> Option<u8> {
+        let snapshot = self.state.snapshot.read().unwrap();
+        let layers = self.state.layers.lock().unwrap();
+        snapshot.button(button).then(|| layers.pressed_in(button))
+    }
+
+    /// Makes a button shift the others into a layer, replacing any modifier on the same button.
+    pub fn add_shift_modifier(
+        &self,
+        modifier: ShiftModifier<D::Buttons>,
+    ) -> Option<ShiftModifier<D::Buttons>> {
+        self.state.layers.lock().unwrap().insert(modifier)
+    }
+
+    pub fn remove_shift_modifier(&self, button: D::Buttons) -> Option<ShiftModifier<D::Buttons>> {
+        self.state.layers.lock().unwrap().remove(button)
+    }
+
+    /// Colors shown instead of the ones from [`VirpilDevice::set_led`] while `layer` is active, an empty map clears
+    /// them.
+    pub fn set_layer_colors(&self, layer: u8, colors: HashMap<D::Led, Color>) -> VirpilResult<()> {
+        self.led_write
+            .send(WriteCommand::LayerColors(layer, colors))
+            .map_err(|_| VirpilError::WorkerStopped)
+    }
+
+    /// Runs `effect` on `led` from its start, shown over the color from [`VirpilDevice::set_led`] until stopped.
+    pub fn animate(&self, led: D::Led, effect: Effect) -> Option<Effect> {
+        self.state
+            .animations
+            .lock()
+            .unwrap()
+            .start(led, effect, Instant::now())
+    }
+
+    /// Stops the effect on `led`, which goes back to its regular color on the next tick.
+    pub fn stop_animation(&self, led: D::Led) -> Option<Effect> {
+        self.state.animations.lock().unwrap().stop(led)
+    }
+
+    pub fn animation(&self, led: D::Led) -> Option<Effect> {
+        self.state.animations.lock().unwrap().effect(led)
+    }
+
+    /// Makes `led` follow a button from the next report on, shown over the color from [`VirpilDevice::set_led`].
+    pub fn bind_led(
+        &self,
+        led: D::Led,
+        binding: LedBinding<D::Buttons>,
+    ) -> VirpilResult<Option<LedBinding<D::Buttons>>> {
+        let snapshot = self.state.snapshot.read().unwrap();
+        let mut bindings = self.state.led_bindings.lock().unwrap();
+        let (old, color) = bindings.insert(led, binding, &snapshot);
+        let _binding_writes = self.state.led_binding_writes.lock().unwrap();
+        drop(bindings);
+        drop(snapshot);
+        self.led_write
+            .send(WriteCommand::Bound(led, Some(color)))
+            .map_err(|_| VirpilError::WorkerStopped)?;
+        Ok(old)
+    }
+
+    /// Hands `led` back to the color from [`VirpilDevice::set_led`].
+    pub fn unbind_led(&self, led: D::Led) -> VirpilResult<Option<LedBinding<D::Buttons>>> {
+        let mut bindings = self.state.led_bindings.lock().unwrap();
+        let old = bindings.remove(led);
+        // Taken before the binding is released so the reader can't queue a color of the old binding after this.
+        let _binding_writes = self.state.led_binding_writes.lock().unwrap();
+        drop(bindings);
+        self.led_write
+            .send(WriteCommand::Bound(led, None))
+            .map_err(|_| VirpilError::WorkerStopped)?;
+        Ok(old)
+    }
+
+    pub fn led_binding(&self, led: D::Led) -> Option<LedBinding<D::Buttons>> {
+        self.state.led_bindings.lock().unwrap().get(led)
+    }
+
+    pub fn set_led(&mut self, led: D::Led, color: Color) -> VirpilResult<Color> {
         if self.led_states.get(&led).unwrap() != &color {
-            self.led_write.send((led, color)).unwrap();
+            self.led_write
+                .send(WriteCommand::Led(led, color))
+                .map_err(|_| VirpilError::WorkerStopped)?;
             Ok(self
                 .led_states
                 .insert(led, color)
@@ -119,74 +882,618 @@ where
         }
     }
 
+    /// Sets several LEDs at once, only sending the ones that changed. With
+    /// [`VirpilDevice::set_batched_led_reports`] the LEDs of one board share a report.
+    pub fn set_leds(&mut self, leds: &[(D::Led, Color)]) -> VirpilResult<()> {
+        let frame: Vec<_> = leds
+            .iter()
+            .filter(|(led, color)| self.led_states.get(led) != Some(color))
+            .copied()
+            .collect();
+        if frame.is_empty() {
+            return Ok(());
+        }
+        self.led_write
+            .send(WriteCommand::Leds(frame.clone()))
+            .map_err(|_| VirpilError::WorkerStopped)?;
+        self.led_states.extend(frame);
+        Ok(())
+    }
+
+    /// Sets every LED to `color`. With [`VirpilDevice::set_broadcast_all_leds`] this is a single report for all
+    /// boards, unless an LED it would change shows a binding, layer or animation color instead.
+    pub fn set_all_leds(&mut self, color: Color) -> VirpilResult<()> {
+        if self.led_states.values().all(|state| *state == color) {
+            return Ok(());
+        }
+        self.led_write
+            .send(WriteCommand::AllLeds(color))
+            .map_err(|_| VirpilError::WorkerStopped)?;
+        self.led_states
+            .values_mut()
+            .for_each(|state| *state = color);
+        Ok(())
+    }
+
+    pub fn broadcast_all_leds(&self) -> bool {
+        self.state.broadcast_all_leds.load(Ordering::Relaxed)
+    }
+
+    /// Sends [`VirpilDevice::set_all_leds`] as one [`BoardType::Default`] report, the Configurator's
+    /// `cnt_led_rgb_all`. The writer goes back to one report per LED the first time the device refuses it but takes
+    /// the same color alone, counted in [`DeviceStatistics::led_broadcast_fallbacks`].
+    pub fn set_broadcast_all_leds(&self, broadcast: bool) {
+        self.state
+            .broadcast_all_leds
+            .store(broadcast, Ordering::Relaxed);
+    }
+
+    pub fn batched_led_reports(&self) -> bool {
+        self.state.batch_led_reports.load(Ordering::Relaxed)
+    }
+
+    /// Fills the slots of every pending LED on a board into a single report instead of one report per LED. Not all
+    /// firmware accepts this, the writer switches back to single reports the first time the device refuses one but
+    /// takes the same color alone, counted in [`DeviceStatistics::led_batch_fallbacks`].
+    pub fn set_batched_led_reports(&self, batched: bool) {
+        self.state
+            .batch_led_reports
+            .store(batched, Ordering::Relaxed);
+    }
+
+    /// Commands waiting for the LED writer.
     pub fn send_queue_size(&self) -> usize {
         self.led_write.len()
     }
 
-    fn state_read_loop(state: Arc<State<D>>, state_read: HidDevice) {
+    /// LEDs whose newest color has not been written yet, mostly held back by the packet rate limit.
+    pub fn pending_led_writes(&self) -> usize {
+        self.state.pending_led_writes.load(Ordering::Relaxed)
+    }
+
+    /// `None` when LED packets are sent as fast as the device takes them.
+    pub fn max_packet_rate(&self) -> Option<f32> {
+        let interval = self.state.min_packet_interval();
+        (!interval.is_zero()).then(|| 1.0 / interval.as_secs_f32())
+    }
+
+    /// Limits LED packets to `packets_per_second`, colors changing faster than that are coalesced.
+    pub fn set_max_packet_rate(&self, packets_per_second: Option<f32>) {
+        let interval = packets_per_second
+            .filter(|rate| *rate > 0.0)
+            .map_or(0, |rate| {
+                // Rates too small for a `Duration` or nanosecond count saturate to the longest interval.
+                Duration::try_from_secs_f32(1.0 / rate).map_or(u64::MAX, |interval| {
+                    interval.as_nanos().min(u64::MAX as u128) as u64
+                })
+            });
+        self.state
+            .min_packet_interval
+            .store(interval, Ordering::Relaxed);
+    }
+
+    pub fn statistics(&self) -> DeviceStatistics {
+        self.state.statistics.get()
+    }
+
+    /// Receives every button and axis change from now on.
+    pub fn subscribe(&self) -> Receiver<DeviceEvent<D>> {
+        self.state.events.subscribe()
+    }
+
+    /// Starts writing every input report and LED packet of this device to `recorder`.
+    pub fn record_to(&self, recorder: Recorder) -> Option<Recorder> {
+        self.state.recorder.write().unwrap().replace(recorder)
+    }
+
+    pub fn stop_recording(&self) -> Option<Recorder> {
+        self.state.recorder.write().unwrap().take()
+    }
+
+    fn state_read_loop(
+        state: Arc<State<D>>,
+        mut state_read: T,
+        mut reconnect: Option<Reconnect<T>>,
+        write_sender: Sender<WriteCommand<D::Led, T>>,
+    ) {
         let mut buffer = [0; 64];
+        let mut sequence = 0;
         while !state.stop.load(Ordering::Relaxed) {
-            match state_read.read(&mut buffer) {
-                Ok(count)
-                    if count
-                        == 1 + D::Axis::COUNT * 2
-                            + D::Buttons::COUNT / 8
-                            + (D::Buttons::COUNT % 8 > 0) as usize =>
-                {
-                    let mut data = &buffer[1..];
-                    for axis in state.axis.iter() {
-                        let (val, rest) = data.split_array_ref();
-                        data = rest;
-                        axis.store(u16::from_le_bytes(*val), Ordering::SeqCst);
+            let result = state_read.read(&mut buffer);
+            let recorder = state.recorder.read().unwrap();
+            if let (Ok(count @ 1..), Some(recorder)) = (&result, &*recorder) {
+                recorder.record(
+                    D::PID,
+                    &state.selector,
+                    PacketKind::Input,
+                    &buffer[..*count],
+                );
+            }
+            drop(recorder);
+            match result {
+                Ok(0) => {
+                    let now = Instant::now();
+                    let mut changes = Vec::new();
+                    state.gestures.lock().unwrap().tick(now, &mut changes);
+                    let layers = state.layers.lock().unwrap();
+                    let events: Vec<_> = changes
+                        .into_iter()
+                        .map(|kind| InputEvent {
+                            time: now,
+                            layer: layers.event_layer(&kind),
+                            kind,
+                        })
+                        .collect();
+                    drop(layers);
+                    state.events.publish(&events);
+                }
+                Ok(count) => match Snapshot::<D>::from_report(
+                    sequence + 1,
+                    Instant::now(),
+                    &buffer[1..count],
+                ) {
+                    Some(snapshot) => {
+                        sequence = snapshot.sequence;
+                        state.statistics.report_received(snapshot.time);
+                        // Queued once the locks are released.
+                        let mut writes = Vec::new();
+                        let (events, binding_writes): (Vec<_>, _) = {
+                            let mut current = state.snapshot.write().unwrap();
+                            let mut changes = current.diff(&snapshot);
+                            let mut layers = state.layers.lock().unwrap();
+                            if layers.update(&current, &snapshot) {
+                                writes.push(WriteCommand::Layer(layers.layer()));
+                            }
+                            let mut bindings = state.led_bindings.lock().unwrap();
+                            for (led, color) in bindings.update(&current, &snapshot) {
+                                writes.push(WriteCommand::Bound(led, Some(color)));
+                            }
+                            state.encoders.lock().unwrap().update(&current, &snapshot);
+                            state.gestures.lock().unwrap().update(
+                                &current,
+                                &snapshot,
+                                &mut changes,
+                            );
+                            state.guarded_switches.lock().unwrap().update(
+                                &current,
+                                &snapshot,
+                                &mut changes,
+                            );
+                            *current = snapshot;
+                            state.hats.lock().unwrap().update(&current);
+                            state
+                                .selectors
+                                .lock()
+                                .unwrap()
+                                .update(&current, &mut changes);
+                            let mut pipeline = state.axis_pipeline.lock().unwrap();
+                            pipeline.update(&current.axis);
+                            let mut virtual_buttons = state.virtual_buttons.lock().unwrap();
+                            virtual_buttons.update(&pipeline, &mut changes);
+                            current.virtual_buttons = virtual_buttons.states();
+                            let events = changes
+                                .into_iter()
+                                .map(|kind| InputEvent {
+                                    time: current.time,
+                                    layer: layers.event_layer(&kind),
+                                    kind,
+                                })
+                                .collect();
+                            // Taken before `bindings` is released so an unbind can't get between these colors and
+                            // the queue.
+                            (events, state.led_binding_writes.lock().unwrap())
+                        };
+                        for write in writes {
+                            let _ = write_sender.send(write);
+                        }
+                        drop(binding_writes);
+                        state.events.publish(&events);
+                    }
+                    None => {
+                        state.statistics.malformed_report();
+                        eprintln!(
+                            "Weird account data length ({}) from {}: {:?}",
+                            count,
+                            product_name(&state_read),
+                            &buffer[..count]
+                        )
                     }
-                    for button in state.buttons.iter() {
-                        let (val, rest) = data.split_array_ref::<1>();
-                        data = rest;
-                        button.store(val[0], Ordering::SeqCst);
+                },
+                Err(error) => {
+                    state.statistics.read_error();
+                    eprintln!(
+                        "Error on {} read, treating as disconnected: {}",
+                        product_name(&state_read),
+                        error
+                    );
+                    state.connected.store(false, Ordering::Release);
+                    if let Some(led_write) =
+                        Self::wait_for_reconnect(&state, &mut state_read, &mut reconnect)
+                    {
+                        let _ = write_sender.send(WriteCommand::Reconnected(led_write));
                     }
                 }
-                Ok(count) => eprintln!(
-                    "Weird account data length ({}) from {}: {:?}",
-                    count,
-                    state_read.get_product_string().unwrap().unwrap(),
-                    &buffer[..count]
-                ),
-                Err(error) => eprintln!(
-                    "Error on {} read: {}",
-                    state_read.get_product_string().unwrap().unwrap(),
-                    error
-                ),
             };
         }
     }
 
-    fn led_write_loop(led_write: HidDevice, write_receiver: Receiver<(D::Led, Color)>) {
-        while let Ok(command) = write_receiver.recv() {
-            let (board_type, led_number) = command.0.to_board_and_led_number();
-            if let Err(error) = send_command(&led_write, board_type, led_number, command.1) {
-                println!(
-                    "Error Setting {} led {} on board {:?} to {:?}! {}",
-                    led_write.get_product_string().unwrap().unwrap(),
-                    led_number,
-                    board_type,
-                    command.1,
-                    error
+    /// Blocks until the device is reopened and returns its new LED interface, or until stopped. Without `reconnect`
+    /// the device is lost, the handle of an unplugged device doesn't read again even once it is back.
+    fn wait_for_reconnect(
+        state: &State<D>,
+        state_read: &mut T,
+        reconnect: &mut Option<Reconnect<T>>,
+    ) -> Option<T> {
+        let reconnect = match reconnect {
+            Some(reconnect) => reconnect,
+            None => {
+                state.lost.store(true, Ordering::Release);
+                while !state.stop.load(Ordering::Relaxed) {
+                    sleep(STOP_POLL_INTERVAL);
+                }
+                return None;
+            }
+        };
+        loop {
+            let start = Instant::now();
+            while start.elapsed() < RECONNECT_INTERVAL {
+                if state.stop.load(Ordering::Relaxed) {
+                    return None;
+                }
+                sleep(STOP_POLL_INTERVAL);
+            }
+            if let Ok((new_state_read, led_write)) = reconnect() {
+                *state_read = new_state_read;
+                state.connected.store(true, Ordering::Release);
+                return Some(led_write);
+            }
+        }
+    }
+
+    /// Applies every queued command before writing, so only the newest color of each LED is sent. LEDs are
+    /// written round robin, at most one packet per configured interval.
+    fn led_write_loop(
+        state: Arc<State<D>>,
+        mut led_write: T,
+        write_receiver: Receiver<WriteCommand<D::Led, T>>,
+        led_states: HashMap<D::Led, Color>,
+    ) {
+        let leds: Vec<_> = D::Led::iter().collect();
+        let mut colors = LedColors::new(led_states.clone());
+        // The starting colors were sent before the writer started.
+        let mut written = led_states;
+        let mut cursor = 0;
+        let mut next_write = Instant::now();
+        let mut open = true;
+        // Newest color from `WriteCommand::AllLeds` that was not broadcast yet.
+        let mut broadcast = None;
+        loop {
+            let stopping = state.stop.load(Ordering::Relaxed);
+            // Colors replaced while the last packet was written are never sent.
+            for command in write_receiver.try_iter() {
+                Self::receive_command(
+                    &state,
+                    command,
+                    &mut led_write,
+                    &mut colors,
+                    &mut written,
+                    &mut broadcast,
+                    stopping,
+                );
+            }
+            let dirty: Vec<_> = if state.connected.load(Ordering::Acquire) {
+                (0..leds.len())
+                    .map(|offset| (cursor + offset) % leds.len())
+                    .filter(|index| {
+                        written.get(&leds[*index]) != Some(&colors.shown(leds[*index], stopping))
+                    })
+                    .collect()
+            } else {
+                // Every LED gets written once reconnected, a broadcast from before would be stale by then.
+                broadcast = None;
+                Vec::new()
+            };
+            state
+                .pending_led_writes
+                .store(dirty.len(), Ordering::Relaxed);
+            if let Some(&index) = dirty.first() {
+                let now = Instant::now();
+                if now >= next_write {
+                    if let Some(color) = broadcast.take() {
+                        let shown_in_color: Vec<_> = dirty
+                            .iter()
+                            .map(|index| leds[*index])
+                            .filter(|led| colors.shown(*led, stopping) == color)
+                            .collect();
+                        // Only sent if no LED it changes shows another color, which it would flash first.
+                        let fits = leds.iter().all(|led| {
+                            written.get(led) == Some(&color)
+                                || colors.shown(*led, stopping) == color
+                        });
+                        if fits
+                            && shown_in_color.len() > 1
+                            && state.broadcast_all_leds.load(Ordering::Relaxed)
+                        {
+                            let mut sent = Self::write_all_leds(&state, &led_write, color);
+                            if sent {
+                                written.extend(leds.iter().map(|led| (*led, color)));
+                            } else if state.connected.load(Ordering::Acquire) {
+                                let led = shown_in_color[0];
+                                sent = Self::write_led(&state, &led_write, led, color);
+                                if sent {
+                                    println!(
+                                        "{} refused a broadcast led report, falling back to one report per led!",
+                                        product_name(&led_write)
+                                    );
+                                    state.broadcast_all_leds.store(false, Ordering::Relaxed);
+                                    state.statistics.led_broadcast_fallback();
+                                    written.insert(led, color);
+                                }
+                            }
+                            next_write = Self::next_write(&state, now, sent);
+                            continue;
+                        }
+                    }
+                    let led = leds[index];
+                    let (board_type, _) = led.to_board_and_led_number();
+                    let batch: Vec<_> = if state.batch_led_reports.load(Ordering::Relaxed) {
+                        dirty
+                            .iter()
+                            .map(|index| leds[*index])
+                            .filter(|led| led.to_board_and_led_number().0 == board_type)
+                            .map(|led| (led, colors.shown(led, stopping)))
+                            .collect()
+                    } else {
+                        Vec::new()
+                    };
+                    // LEDs the device refused stay dirty and are written again.
+                    let sent = if batch.len() > 1 {
+                        if Self::write_leds(&state, &led_write, board_type, &batch) {
+                            written.extend(batch);
+                            true
+                        } else if state.connected.load(Ordering::Acquire) {
+                            // Batching is only given up on if the device takes the same color alone, a device
+                            // that is going away refuses both.
+                            let color = batch[0].1;
+                            cursor = (index + 1) % leds.len();
+                            let sent = Self::write_led(&state, &led_write, led, color);
+                            if sent {
+                                println!(
+                                    "{} refused a batched led report, falling back to one report per led!",
+                                    product_name(&led_write)
+                                );
+                                state.batch_led_reports.store(false, Ordering::Relaxed);
+                                state.statistics.led_batch_fallback();
+                                written.insert(led, color);
+                            }
+                            sent
+                        } else {
+                            false
+                        }
+                    } else {
+                        let color = colors.shown(led, stopping);
+                        cursor = (index + 1) % leds.len();
+                        let sent = Self::write_led(&state, &led_write, led, color);
+                        if sent {
+                            written.insert(led, color);
+                        }
+                        sent
+                    };
+                    next_write = Self::next_write(&state, now, sent);
+                    if !sent && !open {
+                        // The device is being dropped, retrying a device that refuses would keep it from closing.
+                        break;
+                    }
+                    continue;
+                }
+                if !open {
+                    sleep(next_write - now);
+                    continue;
+                }
+            } else if !open {
+                break;
+            }
+            let command = if dirty.is_empty() {
+                write_receiver
+                    .recv()
+                    .map_err(|_| RecvTimeoutError::Disconnected)
+            } else {
+                write_receiver.recv_deadline(next_write)
+            };
+            match command {
+                Ok(command) => Self::receive_command(
+                    &state,
+                    command,
+                    &mut led_write,
+                    &mut colors,
+                    &mut written,
+                    &mut broadcast,
+                    stopping,
+                ),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => open = false,
+            }
+        }
+    }
+
+    /// When the writer may send again after a packet at `now`, not sooner than [`LED_RETRY_INTERVAL`] after a refused
+    /// one.
+    fn next_write(state: &State<D>, now: Instant, sent: bool) -> Instant {
+        let interval = state.min_packet_interval();
+        now + if sent {
+            interval
+        } else {
+            interval.max(LED_RETRY_INTERVAL)
+        }
+    }
+
+    /// Takes a command off the queue, tracking a pending broadcast in `broadcast`.
+    fn receive_command(
+        state: &State<D>,
+        command: WriteCommand<D::Led, T>,
+        led_write: &mut T,
+        colors: &mut LedColors<D::Led>,
+        written: &mut HashMap<D::Led, Color>,
+        broadcast: &mut Option<Color>,
+        stopping: bool,
+    ) {
+        match command {
+            WriteCommand::Reconnected(new_led_write) => {
+                *led_write = new_led_write;
+                written.clear();
+            }
+            WriteCommand::AllLeds(color) => {
+                Self::apply_command(
+                    state,
+                    colors,
+                    written,
+                    WriteCommand::AllLeds(color),
+                    stopping,
+                );
+                *broadcast = Some(color);
+            }
+            // Base colors set after a broadcast no longer match it.
+            WriteCommand::Led(led, color) => {
+                *broadcast = None;
+                Self::apply_command(
+                    state,
+                    colors,
+                    written,
+                    WriteCommand::Led(led, color),
+                    stopping,
                 );
             }
+            WriteCommand::Leds(frame) => {
+                *broadcast = None;
+                for (led, color) in frame {
+                    Self::apply_command(
+                        state,
+                        colors,
+                        written,
+                        WriteCommand::Led(led, color),
+                        stopping,
+                    );
+                }
+            }
+            command => Self::apply_command(state, colors, written, command, stopping),
+        }
+    }
+
+    /// Applies `command` to `colors`, counting a color that got replaced before it was ever written.
+    fn apply_command(
+        state: &State<D>,
+        colors: &mut LedColors<D::Led>,
+        written: &HashMap<D::Led, Color>,
+        command: WriteCommand<D::Led, T>,
+        stopping: bool,
+    ) {
+        let led = command.led();
+        let before = led.map(|led| colors.shown(led, stopping));
+        colors.apply(command);
+        if let (Some(led), Some(before)) = (led, before) {
+            if written.get(&led) != Some(&before) && colors.shown(led, stopping) != before {
+                state.statistics.led_update_coalesced();
+            }
+        }
+    }
+
+    fn animation_loop(state: Arc<State<D>>, write_sender: Sender<WriteCommand<D::Led, T>>) {
+        while !state.stop.load(Ordering::Relaxed) {
+            sleep(ANIMATION_TICK);
+            let changes = state.animations.lock().unwrap().tick(Instant::now());
+            for (led, color) in changes {
+                if write_sender
+                    .send(WriteCommand::Animated(led, color))
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Returns false if the device refused the report.
+    fn write_led(state: &State<D>, led_write: &T, led: D::Led, color: Color) -> bool {
+        let (board_type, led_number) = led.to_board_and_led_number();
+        let packet = packet_for_command(board_type, led_number, color);
+        if let Err(error) = Self::send_led_packet(state, led_write, &packet) {
+            println!(
+                "Error Setting {} led {} on board {:?} to {:?}! {}",
+                product_name(led_write),
+                led_number,
+                board_type,
+                color,
+                error
+            );
+            return false;
+        }
+        true
+    }
+
+    /// Writes several LEDs of `board_type` in one report, returning false if the device refused it.
+    fn write_leds(
+        state: &State<D>,
+        led_write: &T,
+        board_type: BoardType,
+        batch: &[(D::Led, Color)],
+    ) -> bool {
+        let slots: Vec<_> = batch
+            .iter()
+            .map(|(led, color)| (led.to_board_and_led_number().1, *color))
+            .collect();
+        let packet = packet_for_leds(board_type, &slots);
+        if let Err(error) = Self::send_led_packet(state, led_write, &packet) {
+            println!(
+                "Error Setting {} leds {:?} on board {:?}! {}",
+                product_name(led_write),
+                slots,
+                board_type,
+                error
+            );
+            return false;
+        }
+        true
+    }
+
+    /// Sets every LED of every board with one [`BoardType::Default`] report, returning false if the device refused
+    /// it.
+    fn write_all_leds(state: &State<D>, led_write: &T, color: Color) -> bool {
+        let packet = packet_for_command(BoardType::Default, 0, color);
+        if let Err(error) = Self::send_led_packet(state, led_write, &packet) {
+            println!(
+                "Error Setting all {} leds to {:?}, falling back to one report per led! {}",
+                product_name(led_write),
+                color,
+                error
+            );
+            return false;
+        }
+        true
+    }
+
+    fn send_led_packet(state: &State<D>, led_write: &T, packet: &[u8; 38]) -> HidResult<()> {
+        if let Some(recorder) = &*state.recorder.read().unwrap() {
+            recorder.record(D::PID, &state.selector, PacketKind::Led, packet);
+        }
+        let result = led_write.send_feature_report(packet);
+        if result.is_err() {
+            state.statistics.led_write_failure();
+        } else {
+            state.statistics.led_packet_sent();
         }
+        result
     }
 }
-impl<D> Drop for VirpilDevice<D>
+impl<D, T> Drop for VirpilDevice<D, T>
 where
     D: VirpilDeviceDescription + 'static,
+    T: HidTransport,
     [(); D::Axis::COUNT]:,
     [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
 {
     fn drop(&mut self) {
         self.state.stop.store(true, Ordering::Relaxed);
-        for led in D::Led::iter() {
-            let _ = self.set_led(led, LedPower::DEFAULT_RED);
-        }
+        let _ = self.set_all_leds(LedPower::DEFAULT_RED);
         unsafe { ManuallyDrop::drop(&mut self.led_write) }
         let handles = self.threads.take().unwrap();
         for handle in handles {
@@ -195,6 +1502,16 @@ where
     }
 }
 
+fn product_name<T>(device: &T) -> String
+where
+    T: HidTransport,
+{
+    match device.get_product_string() {
+        Ok(Some(name)) => name,
+        _ => "<unknown device>".to_owned(),
+    }
+}
+
 pub struct State<D>
 where
     D: VirpilDeviceDescription,
@@ -202,8 +1519,34 @@ where
     [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
 {
     stop: AtomicBool,
-    axis: [AtomicU16; D::Axis::COUNT],
-    buttons: [AtomicU8; D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize],
+    connected: AtomicBool,
+    /// Set once disconnected without a way to reconnect.
+    lost: AtomicBool,
+    selector: DeviceSelector,
+    recorder: RwLock<Option<Recorder>>,
+    events: EventPublisher<DeviceEvent<D>>,
+    snapshot: RwLock<Snapshot<D>>,
+    statistics: StatisticsCounters,
+    axis_pipeline: Mutex<AxisPipeline<D>>,
+    virtual_buttons: Mutex<VirtualButtons<D>>,
+    encoders: Mutex<Encoders<D>>,
+    hats: Mutex<Hats<D>>,
+    selectors: Mutex<Selectors<D>>,
+    guarded_switches: Mutex<GuardedSwitches<D>>,
+    gestures: Mutex<Gestures<D>>,
+    layers: Mutex<ShiftLayers<D>>,
+    animations: Mutex<Animations<D>>,
+    led_bindings: Mutex<LedBindings<D>>,
+    /// Held while binding colors are queued, after [`State::led_bindings`], so every color is queued in the order the
+    /// bindings changed.
+    led_binding_writes: Mutex<()>,
+    /// Minimum time between LED packets in nanoseconds, `0` for no limit.
+    min_packet_interval: AtomicU64,
+    pending_led_writes: AtomicUsize,
+    /// Whether LEDs sharing a board are written in one report.
+    batch_led_reports: AtomicBool,
+    /// Whether [`VirpilDevice::set_all_leds`] is sent as a single [`BoardType::Default`] report.
+    broadcast_all_leds: AtomicBool,
 }
 impl<D> Default for State<D>
 where
@@ -214,11 +1557,41 @@ where
     fn default() -> Self {
         Self {
             stop: AtomicBool::new(false),
-            axis: array_init(|_| AtomicU16::new(0)),
-            buttons: array_init(|_| AtomicU8::new(0)),
+            connected: AtomicBool::new(true),
+            lost: AtomicBool::new(false),
+            selector: DeviceSelector::Any,
+            recorder: RwLock::new(None),
+            events: EventPublisher::default(),
+            snapshot: RwLock::new(Snapshot::new(Instant::now())),
+            statistics: StatisticsCounters::default(),
+            axis_pipeline: Mutex::default(),
+            virtual_buttons: Mutex::default(),
+            encoders: Mutex::default(),
+            hats: Mutex::default(),
+            selectors: Mutex::default(),
+            guarded_switches: Mutex::default(),
+            gestures: Mutex::default(),
+            layers: Mutex::default(),
+            animations: Mutex::default(),
+            led_bindings: Mutex::default(),
+            led_binding_writes: Mutex::default(),
+            min_packet_interval: AtomicU64::new(0),
+            pending_led_writes: AtomicUsize::new(0),
+            batch_led_reports: AtomicBool::new(false),
+            broadcast_all_leds: AtomicBool::new(false),
         }
     }
 }
+impl<D> State<D>
+where
+    D: VirpilDeviceDescription,
+    [(); D::Axis::COUNT]:,
+    [(); D::Buttons::COUNT / 8 + (D::Buttons::COUNT % 8 > 0) as usize]:,
+{
+    fn min_packet_interval(&self) -> Duration {
+        Duration::from_nanos(self.min_packet_interval.load(Ordering::Relaxed))
+    }
+}
 
 #[derive(EnumCount, EnumIter, Debug, Copy, Clone, Eq, PartialEq, Hash)]
 #[repr(u8)]
@@ -263,3 +1636,629 @@ impl ToBoardAndLedNumber for AllAddBoard {
         (BoardType::AddBoard, *self as u8)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use hidapi::HidError;
+
+    use super::*;
+    use crate::left_panel::{LeftPanel, LeftPanelButtons, LeftPanelLed};
+    use crate::right_stick::{RightStick, RightStickAxis};
+    use crate::simulator::{wait_until, SimulatedBackend, SimulatedDevice, SimulatedTransport};
+
+    /// Lists fixed interfaces, nothing can be opened.
+    struct FixedBackend(Vec<InterfaceInfo>);
+    impl HidBackend for FixedBackend {
+        type Device = SimulatedTransport;
+
+        fn interfaces(&self) -> Vec<InterfaceInfo> {
+            self.0.clone()
+        }
+
+        fn open(&self, interface: &InterfaceInfo) -> HidResult<Self::Device> {
+            Err(HidError::HidApiError {
+                message: format!("Can't open {:?}", interface.path),
+            })
+        }
+    }
+
+    fn interface(path: &str, usage: u16, serial_number: Option<&str>) -> InterfaceInfo {
+        InterfaceInfo {
+            path: CString::new(path).unwrap(),
+            vendor_id: VIRPIL_VID,
+            product_id: 0x8194,
+            usage,
+            serial_number: serial_number.map(str::to_owned),
+            product_string: None,
+        }
+    }
+
+    fn group_sizes(interfaces: Vec<InterfaceInfo>) -> Vec<usize> {
+        physical_devices(&FixedBackend(interfaces))
+            .iter()
+            .map(|device| device.interfaces.len())
+            .collect()
+    }
+
+    #[test]
+    fn macos_devices_group_by_serial_number() {
+        let service = "IOService:/AppleACPIPlatformExpert/PCI0@0/XHC1@14";
+        assert_eq!(
+            group_sizes(vec![
+                interface(
+                    &format!("{}/HS01/IOUSBHostInterface@0", service),
+                    4,
+                    Some("A1")
+                ),
+                interface(
+                    &format!("{}/HS01/IOUSBHostInterface@1", service),
+                    1,
+                    Some("A1")
+                ),
+                interface(
+                    &format!("{}/HS02/IOUSBHostInterface@0", service),
+                    4,
+                    Some("B2")
+                ),
+                interface(
+                    &format!("{}/HS02/IOUSBHostInterface@1", service),
+                    1,
+                    Some("B2")
+                ),
+            ]),
+            vec![2, 2]
+        );
+    }
+
+    #[test]
+    fn hidraw_devices_group_by_serial_number() {
+        assert_eq!(
+            group_sizes(vec![
+                interface("/dev/hidraw0", 4, Some("A1")),
+                interface("/dev/hidraw1", 1, Some("A1")),
+                interface("/dev/hidraw2", 4, Some("B2")),
+                interface("/dev/hidraw3", 1, Some("B2")),
+            ]),
+            vec![2, 2]
+        );
+    }
+
+    #[test]
+    fn windows_devices_group_by_serial_number() {
+        assert_eq!(
+            group_sizes(vec![
+                interface(
+                    "\\\\?\\hid#vid_3344&pid_8194&mi_00#7&1a&0&0000#{4d1e55b2}",
+                    4,
+                    Some("A1")
+                ),
+                interface(
+                    "\\\\?\\hid#vid_3344&pid_8194&mi_01#7&1b&0&0000#{4d1e55b2}",
+                    1,
+                    Some("A1")
+                ),
+            ]),
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn libusb_devices_without_serial_group_by_path_prefix() {
+        assert_eq!(
+            group_sizes(vec![
+                interface("0001:0005:00", 4, None),
+                interface("0001:0005:01", 1, None),
+                interface("0001:0006:00", 4, Some("")),
+                interface("0001:0006:01", 1, Some("")),
+            ]),
+            vec![2, 2]
+        );
+    }
+
+    #[test]
+    fn interfaces_without_serial_or_prefix_stay_apart() {
+        assert_eq!(
+            group_sizes(vec![
+                interface("/dev/hidraw0", 4, None),
+                interface("/dev/hidraw1", 1, None),
+            ]),
+            vec![1, 1]
+        );
+    }
+
+    /// The simulated device's interfaces with their usages replaced by `usages`, repeating the last interface for
+    /// extra ones.
+    fn with_usages(backend: &SimulatedBackend, usages: &[u16]) -> PhysicalDevice {
+        let mut device = physical_devices(backend).remove(0);
+        let last = device.interfaces.last().unwrap().clone();
+        device.interfaces.resize(usages.len(), last);
+        for (interface, usage) in device.interfaces.iter_mut().zip(usages) {
+            interface.usage = *usage;
+        }
+        device
+    }
+
+    #[test]
+    fn interfaces_are_checked_when_opening() {
+        let backend = SimulatedBackend::new();
+        backend.add_device::<LeftPanel>();
+        assert!(with_usages(&backend, &[4, 1]).open(&backend).is_ok());
+        assert!(matches!(
+            with_usages(&backend, &[4, 2]).open(&backend),
+            Err(VirpilError::UnexpectedUsage { usage: 2, .. })
+        ));
+        assert!(matches!(
+            with_usages(&backend, &[4]).open(&backend),
+            Err(VirpilError::LedInterfaceMissing { .. })
+        ));
+        assert!(matches!(
+            with_usages(&backend, &[1]).open(&backend),
+            Err(VirpilError::InputInterfaceMissing { .. })
+        ));
+        assert!(matches!(
+            with_usages(&backend, &[4, 1, 1]).open(&backend),
+            Err(VirpilError::DuplicateInterface { usage: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn interfaces_that_fail_to_open_are_hid_errors() {
+        let device = physical_devices(&FixedBackend(vec![
+            interface("/dev/hidraw0", 4, Some("A1")),
+            interface("/dev/hidraw1", 1, Some("A1")),
+        ]))
+        .remove(0);
+        assert!(matches!(
+            device.open(&FixedBackend(Vec::new())),
+            Err(VirpilError::Hid(_))
+        ));
+    }
+
+    /// Whether a device was opened on `simulated`, going by where its starting colors went.
+    fn opened(simulated: &SimulatedDevice<LeftPanel>) -> bool {
+        simulated.wait_for_led_reports(LeftPanelLed::COUNT, Duration::from_millis(100))
+    }
+
+    #[test]
+    fn selectors_pick_one_of_two_identical_devices() {
+        let backend = SimulatedBackend::new();
+        let first = backend.add_device::<LeftPanel>();
+        let second = backend.add_device::<LeftPanel>();
+        let second_device = physical_devices(&backend)
+            .into_iter()
+            .find(|device| device.serial_number.as_deref() == Some(second.serial_number()))
+            .unwrap();
+        let interface_path = second_device.interfaces[1]
+            .path
+            .to_string_lossy()
+            .into_owned();
+        for selector in [
+            DeviceSelector::SerialNumber(second.serial_number().to_owned()),
+            DeviceSelector::Path(second_device.path_prefix.clone()),
+            DeviceSelector::Path(interface_path),
+        ] {
+            first.clear_led_reports();
+            second.clear_led_reports();
+            let device =
+                find_device_by::<LeftPanel, _>(&backend, &selector, LedPower::OFF).unwrap();
+            assert!(opened(&second), "{:?}", selector);
+            assert!(!opened(&first), "{:?}", selector);
+            assert_eq!(device.selector(), &second_device.selector());
+        }
+    }
+
+    #[test]
+    fn unknown_selectors_are_not_found() {
+        let backend = SimulatedBackend::new();
+        backend.add_device::<LeftPanel>();
+        for selector in [
+            DeviceSelector::SerialNumber("missing".to_owned()),
+            DeviceSelector::Path("ffff:ffff".to_owned()),
+        ] {
+            match find_device_by::<LeftPanel, _>(&backend, &selector, LedPower::OFF) {
+                Err(VirpilError::DeviceNotFound {
+                    product_id,
+                    selector: not_found,
+                }) => {
+                    assert_eq!(product_id, LeftPanel::PID);
+                    assert_eq!(not_found, selector);
+                }
+                other => panic!("{:?} found {:?}", selector, other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn find_devices_opens_every_identical_device() {
+        let backend = SimulatedBackend::new();
+        let first = backend.add_device::<LeftPanel>();
+        let second = backend.add_device::<LeftPanel>();
+        let devices = find_devices::<LeftPanel, _>(&backend, LedPower::OFF).unwrap();
+        let selectors: Vec<_> = devices
+            .iter()
+            .map(|device| device.selector().clone())
+            .collect();
+        assert_eq!(
+            selectors,
+            vec![
+                DeviceSelector::SerialNumber(first.serial_number().to_owned()),
+                DeviceSelector::SerialNumber(second.serial_number().to_owned()),
+            ]
+        );
+        assert!(opened(&first));
+        assert!(opened(&second));
+    }
+
+    #[test]
+    fn replugged_devices_reopen_and_resend_their_leds() {
+        let backend = Arc::new(Mutex::new(SimulatedBackend::new()));
+        let mut simulated = backend.lock().unwrap().add_device::<LeftPanel>();
+        let mut device =
+            find_reconnecting_device::<LeftPanel, _>(&backend, LedPower::DEFAULT_RED).unwrap();
+        device
+            .set_led(LeftPanelLed::B1, LedPower::FULL_GREEN)
+            .unwrap();
+        assert!(wait_until(
+            || simulated.led_color(LeftPanelLed::B1) == Some(LedPower::FULL_GREEN)
+        ));
+        simulated.unplug();
+        assert!(wait_until(|| device.status() == DeviceStatus::Disconnected));
+        simulated.clear_led_reports();
+        simulated.plug();
+        sleep(RECONNECT_INTERVAL);
+        assert!(wait_until(|| device.status() == DeviceStatus::Connected));
+        assert!(wait_until(|| LeftPanelLed::iter().all(|led| {
+            let color = match led {
+                LeftPanelLed::B1 => LedPower::FULL_GREEN,
+                _ => LedPower::DEFAULT_RED,
+            };
+            simulated.led_color(led) == Some(color)
+        })));
+        simulated.press(LeftPanelButtons::B2).send_report();
+        assert!(wait_until(|| device.button_state(LeftPanelButtons::B2)));
+    }
+
+    #[test]
+    fn devices_without_reconnect_are_lost_when_unplugged() {
+        let backend = SimulatedBackend::new();
+        let simulated = backend.add_device::<LeftPanel>();
+        let device = find_device::<LeftPanel, _>(&backend, LedPower::DEFAULT_RED).unwrap();
+        assert_eq!(device.status(), DeviceStatus::Connected);
+        simulated.unplug();
+        assert!(wait_until(|| device.status() == DeviceStatus::Lost));
+        simulated.plug();
+        sleep(RECONNECT_INTERVAL);
+        assert_eq!(device.status(), DeviceStatus::Lost);
+    }
+
+    #[test]
+    fn normalized_axes_follow_their_kind() {
+        let backend = SimulatedBackend::new();
+        let mut simulated = backend.add_device::<RightStick>();
+        let device = find_device::<RightStick, _>(&backend, LedPower::OFF).unwrap();
+        simulated
+            .set_axis(RightStickAxis::StickX, 0)
+            .set_axis(RightStickAxis::StickY, MAX_AXIS_VALUE / 2)
+            .set_axis(RightStickAxis::LowerTrigger, 0)
+            .send_report();
+        assert!(wait_until(|| device
+            .axis_normalized(RightStickAxis::StickY)
+            == 0.0));
+        assert_eq!(device.axis_normalized(RightStickAxis::StickX), -1.0);
+        assert_eq!(device.axis_normalized(RightStickAxis::LowerTrigger), 0.0);
+        simulated
+            .set_axis(RightStickAxis::StickX, MAX_AXIS_VALUE)
+            .set_axis(RightStickAxis::LowerTrigger, MAX_AXIS_VALUE)
+            .send_report();
+        assert!(wait_until(|| device
+            .axis_normalized(RightStickAxis::StickX)
+            == 1.0));
+        assert_eq!(device.axis_normalized(RightStickAxis::LowerTrigger), 1.0);
+    }
+
+    #[test]
+    fn dropping_the_device_restores_base_colors_under_overlays() {
+        let backend = SimulatedBackend::new();
+        let simulated = backend.add_device::<LeftPanel>();
+        let device = find_device::<LeftPanel, _>(&backend, LedPower::DEFAULT_RED).unwrap();
+        device
+            .set_layer_colors(
+                BASE_LAYER,
+                HashMap::from([(LeftPanelLed::B1, LedPower::FULL_GREEN)]),
+            )
+            .unwrap();
+        device.animate(
+            LeftPanelLed::B2,
+            Effect::Flash {
+                color: LedPower::FULL_BLUE,
+                duration: Duration::from_secs(60),
+            },
+        );
+        assert!(wait_until(|| {
+            simulated.led_color(LeftPanelLed::B1) == Some(LedPower::FULL_GREEN)
+                && simulated.led_color(LeftPanelLed::B2) == Some(LedPower::FULL_BLUE)
+        }));
+        // Every base color already is the color set on drop.
+        drop(device);
+        for led in LeftPanelLed::iter() {
+            assert_eq!(simulated.led_color(led), Some(LedPower::DEFAULT_RED));
+        }
+    }
+
+    #[test]
+    fn unbound_leds_stop_following_their_button() {
+        let backend = SimulatedBackend::new();
+        let mut simulated = backend.add_device::<LeftPanel>();
+        let device = find_device::<LeftPanel, _>(&backend, LedPower::DEFAULT_RED).unwrap();
+        device
+            .bind_led(
+                LeftPanelLed::B1,
+                LedBinding::held(
+                    LeftPanelButtons::B1,
+                    LedPower::FULL_GREEN,
+                    LedPower::FULL_BLUE,
+                ),
+            )
+            .unwrap();
+        simulated.press(LeftPanelButtons::B1).send_report();
+        assert!(wait_until(
+            || simulated.led_color(LeftPanelLed::B1) == Some(LedPower::FULL_GREEN)
+        ));
+        device.unbind_led(LeftPanelLed::B1).unwrap();
+        simulated.release(LeftPanelButtons::B1).send_report();
+        assert!(wait_until(|| !device.button_state(LeftPanelButtons::B1)));
+        assert!(wait_until(
+            || simulated.led_color(LeftPanelLed::B1) == Some(LedPower::DEFAULT_RED)
+        ));
+    }
+
+    #[test]
+    fn rate_limited_leds_only_write_the_newest_color() {
+        let backend = SimulatedBackend::new();
+        let simulated = backend.add_device::<LeftPanel>();
+        let mut device = find_device::<LeftPanel, _>(&backend, LedPower::DEFAULT_RED).unwrap();
+        assert!(
+            simulated.wait_for_led_reports(LeftPanelLed::iter().count(), Duration::from_secs(1))
+        );
+        device.set_max_packet_rate(Some(5.0));
+        simulated.clear_led_reports();
+        let colors = [
+            LedPower::FULL_BLUE,
+            LedPower::FULL_WHITE,
+            LedPower::FULL_GREEN,
+        ];
+        for color in colors.iter().cycle().take(30) {
+            device.set_led(LeftPanelLed::B1, *color).unwrap();
+        }
+        assert!(wait_until(
+            || simulated.led_color(LeftPanelLed::B1) == Some(LedPower::FULL_GREEN)
+        ));
+        assert!(simulated.led_reports().len() < 30);
+        assert!(device.statistics().led_updates_coalesced > 0);
+    }
+
+    #[test]
+    fn slow_devices_only_get_the_newest_color() {
+        let backend = SimulatedBackend::new();
+        let simulated = backend.add_device::<LeftPanel>();
+        let mut device = find_device::<LeftPanel, _>(&backend, LedPower::DEFAULT_RED).unwrap();
+        assert!(
+            simulated.wait_for_led_reports(LeftPanelLed::iter().count(), Duration::from_secs(1))
+        );
+        simulated.set_led_report_delay(Duration::from_millis(5));
+        simulated.clear_led_reports();
+        let colors = [
+            LedPower::FULL_BLUE,
+            LedPower::FULL_WHITE,
+            LedPower::FULL_GREEN,
+        ];
+        for color in colors.iter().cycle().take(30) {
+            device.set_led(LeftPanelLed::B1, *color).unwrap();
+        }
+        assert!(wait_until(
+            || simulated.led_color(LeftPanelLed::B1) == Some(LedPower::FULL_GREEN)
+        ));
+        assert!(simulated.led_reports().len() < 30);
+        assert!(device.statistics().led_updates_coalesced > 0);
+    }
+
+    #[test]
+    fn tiny_packet_rates_saturate() {
+        let backend = SimulatedBackend::new();
+        backend.add_device::<LeftPanel>();
+        let device = find_device::<LeftPanel, _>(&backend, LedPower::OFF).unwrap();
+        for rate in [1e-12, f32::MIN_POSITIVE] {
+            device.set_max_packet_rate(Some(rate));
+            assert!(device.max_packet_rate().unwrap() > 0.0);
+        }
+        device.set_max_packet_rate(None);
+        assert_eq!(device.max_packet_rate(), None);
+    }
+
+    #[test]
+    fn refused_batches_fall_back_to_single_reports() {
+        let backend = SimulatedBackend::new();
+        let simulated = backend.add_device::<LeftPanel>();
+        let mut device = find_device::<LeftPanel, _>(&backend, LedPower::DEFAULT_RED).unwrap();
+        simulated.set_single_led_reports_only(true);
+        device.set_batched_led_reports(true);
+        let frame: Vec<_> = LeftPanelLed::iter()
+            .map(|led| (led, LedPower::FULL_GREEN))
+            .collect();
+        device.set_leds(&frame).unwrap();
+        assert!(wait_until(|| LeftPanelLed::iter().all(|led| simulated
+            .led_color(led)
+            == Some(LedPower::FULL_GREEN))));
+        assert!(!device.batched_led_reports());
+        assert_eq!(device.statistics().led_batch_fallbacks, 1);
+    }
+
+    #[test]
+    fn refused_leds_are_written_again() {
+        let backend = SimulatedBackend::new();
+        let mut simulated = backend.add_device::<LeftPanel>();
+        let mut device = find_device::<LeftPanel, _>(&backend, LedPower::DEFAULT_RED).unwrap();
+        simulated.refuse_led_reports(2);
+        device
+            .set_led(LeftPanelLed::B1, LedPower::FULL_GREEN)
+            .unwrap();
+        assert!(wait_until(
+            || simulated.led_color(LeftPanelLed::B1) == Some(LedPower::FULL_GREEN)
+        ));
+        assert_eq!(device.statistics().led_write_failures, 2);
+        assert_eq!(device.status(), DeviceStatus::Connected);
+        // Refused while batched, the fallback single report is refused as well.
+        device.set_batched_led_reports(true);
+        simulated.refuse_led_reports(2);
+        let frame: Vec<_> = LeftPanelLed::iter()
+            .map(|led| (led, LedPower::FULL_BLUE))
+            .collect();
+        device.set_leds(&frame).unwrap();
+        assert!(wait_until(|| LeftPanelLed::iter().all(|led| simulated
+            .led_color(led)
+            == Some(LedPower::FULL_BLUE))));
+        assert!(device.batched_led_reports());
+        simulated.press(LeftPanelButtons::B1).send_report();
+        assert!(wait_until(|| device.button_state(LeftPanelButtons::B1)));
+    }
+
+    #[test]
+    fn dropping_a_refusing_device_does_not_hang() {
+        let backend = SimulatedBackend::new();
+        let simulated = backend.add_device::<LeftPanel>();
+        let device = find_device::<LeftPanel, _>(&backend, LedPower::FULL_GREEN).unwrap();
+        simulated.refuse_led_reports(usize::MAX);
+        drop(device);
+    }
+
+    #[test]
+    fn batching_stays_on_when_the_device_goes_away() {
+        let backend = SimulatedBackend::new();
+        let simulated = backend.add_device::<LeftPanel>();
+        let mut device = find_device::<LeftPanel, _>(&backend, LedPower::DEFAULT_RED).unwrap();
+        device.set_batched_led_reports(true);
+        simulated.unplug();
+        let frame: Vec<_> = LeftPanelLed::iter()
+            .map(|led| (led, LedPower::FULL_GREEN))
+            .collect();
+        device.set_leds(&frame).unwrap();
+        assert!(wait_until(|| device.status() == DeviceStatus::Lost));
+        assert!(device.batched_led_reports());
+        assert_eq!(device.statistics().led_batch_fallbacks, 0);
+    }
+
+    fn broadcasts<D: VirpilDeviceDescription>(simulated: &SimulatedDevice<D>) -> usize {
+        simulated
+            .led_reports()
+            .iter()
+            .filter(|report| report[1] == BoardType::Default as u8)
+            .count()
+    }
+
+    #[test]
+    fn all_leds_are_broadcast_in_one_report() {
+        let backend = SimulatedBackend::new();
+        let simulated = backend.add_device::<LeftPanel>();
+        let mut device = find_device::<LeftPanel, _>(&backend, LedPower::DEFAULT_RED).unwrap();
+        assert!(
+            simulated.wait_for_led_reports(LeftPanelLed::iter().count(), Duration::from_secs(1))
+        );
+        simulated.clear_led_reports();
+        device.set_broadcast_all_leds(true);
+        device.set_all_leds(LedPower::FULL_GREEN).unwrap();
+        assert!(wait_until(|| LeftPanelLed::iter().all(|led| simulated
+            .led_color(led)
+            == Some(LedPower::FULL_GREEN))));
+        assert_eq!(simulated.led_reports().len(), 1);
+        assert_eq!(broadcasts(&simulated), 1);
+    }
+
+    #[test]
+    fn broadcasts_skip_leds_shown_in_another_color() {
+        let backend = SimulatedBackend::new();
+        let simulated = backend.add_device::<LeftPanel>();
+        let mut device = find_device::<LeftPanel, _>(&backend, LedPower::DEFAULT_RED).unwrap();
+        device.set_broadcast_all_leds(true);
+        device
+            .set_layer_colors(
+                BASE_LAYER,
+                HashMap::from([(LeftPanelLed::B1, LedPower::FULL_BLUE)]),
+            )
+            .unwrap();
+        assert!(wait_until(
+            || simulated.led_color(LeftPanelLed::B1) == Some(LedPower::FULL_BLUE)
+        ));
+        device.set_all_leds(LedPower::FULL_GREEN).unwrap();
+        assert!(wait_until(|| LeftPanelLed::iter()
+            .filter(|led| *led != LeftPanelLed::B1)
+            .all(
+                |led| simulated.led_color(led) == Some(LedPower::FULL_GREEN)
+            )));
+        assert_eq!(
+            simulated.led_color(LeftPanelLed::B1),
+            Some(LedPower::FULL_BLUE)
+        );
+        assert_eq!(broadcasts(&simulated), 0);
+    }
+
+    #[test]
+    fn base_colors_set_after_a_broadcast_replace_it() {
+        let backend = SimulatedBackend::new();
+        let simulated = backend.add_device::<LeftPanel>();
+        let mut device = find_device::<LeftPanel, _>(&backend, LedPower::DEFAULT_RED).unwrap();
+        device.set_broadcast_all_leds(true);
+        device.set_batched_led_reports(true);
+        device.set_max_packet_rate(Some(20.0));
+        // Holds the next write back, so the broadcast is still pending when B1 changes.
+        device
+            .set_led(LeftPanelLed::B2, LedPower::FULL_WHITE)
+            .unwrap();
+        device.set_all_leds(LedPower::FULL_GREEN).unwrap();
+        device
+            .set_led(LeftPanelLed::B1, LedPower::FULL_BLUE)
+            .unwrap();
+        assert!(wait_until(|| {
+            simulated.led_color(LeftPanelLed::B1) == Some(LedPower::FULL_BLUE)
+                && LeftPanelLed::iter()
+                    .filter(|led| *led != LeftPanelLed::B1)
+                    .all(|led| simulated.led_color(led) == Some(LedPower::FULL_GREEN))
+        }));
+        assert_eq!(broadcasts(&simulated), 0);
+    }
+
+    #[test]
+    fn refused_broadcasts_fall_back_to_single_reports() {
+        let backend = SimulatedBackend::new();
+        let simulated = backend.add_device::<LeftPanel>();
+        let mut device = find_device::<LeftPanel, _>(&backend, LedPower::DEFAULT_RED).unwrap();
+        simulated.set_single_led_reports_only(true);
+        device.set_broadcast_all_leds(true);
+        device.set_all_leds(LedPower::FULL_GREEN).unwrap();
+        assert!(wait_until(|| LeftPanelLed::iter().all(|led| simulated
+            .led_color(led)
+            == Some(LedPower::FULL_GREEN))));
+        assert!(!device.broadcast_all_leds());
+        assert_eq!(device.statistics().led_broadcast_fallbacks, 1);
+    }
+
+    #[test]
+    fn broadcasts_are_dropped_while_disconnected() {
+        let backend = Arc::new(Mutex::new(SimulatedBackend::new()));
+        let simulated = backend.lock().unwrap().add_device::<LeftPanel>();
+        let mut device =
+            find_reconnecting_device::<LeftPanel, _>(&backend, LedPower::DEFAULT_RED).unwrap();
+        device.set_broadcast_all_leds(true);
+        simulated.unplug();
+        assert!(wait_until(|| device.status() == DeviceStatus::Disconnected));
+        device.set_all_leds(LedPower::FULL_GREEN).unwrap();
+        simulated.plug();
+        std::thread::sleep(RECONNECT_INTERVAL);
+        assert!(wait_until(|| LeftPanelLed::iter().all(|led| simulated
+            .led_color(led)
+            == Some(LedPower::FULL_GREEN))));
+        assert!(device.broadcast_all_leds());
+        assert_eq!(broadcasts(&simulated), 0);
+    }
+}
@@ -0,0 +1,254 @@
+use crate::axis::AxisPipeline;
+use crate::events::{DeviceEventKind, InputEventKind};
+use crate::virpil_device::VirpilDeviceDescription;
+
+/// A button that is pressed while an axis sits in a range, the Configurator's `AxisToButton` group.
+///
+/// `start` and `end` are processed axis values in `0..=1`, see
+/// [`VirpilDevice::axis_processed`](crate::virpil_device::VirpilDevice::axis_processed). Once pressed the button is
+/// only released after the axis leaves the range widened by `hysteresis` on both ends, so a value resting on an edge
+/// does not chatter.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VirtualButton<A> {
+    pub name: &'static str,
+    pub axis: A,
+    pub start: f32,
+    pub end: f32,
+    pub hysteresis: f32,
+}
+impl<A> VirtualButton<A> {
+    pub fn new(name: &'static str, axis: A, start: f32, end: f32) -> Self {
+        Self {
+            name,
+            axis,
+            start: start.min(end),
+            end: start.max(end),
+            hysteresis: 0.0,
+        }
+    }
+
+    pub fn with_hysteresis(mut self, hysteresis: f32) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+
+    fn next_state(&self, pressed: bool, value: f32) -> bool {
+        let margin = if pressed { self.hysteresis } else { 0.0 };
+        value >= self.start - margin && value <= self.end + margin
+    }
+}
+
+/// Every virtual button of a device and whether it is pressed, driven by the reader thread.
+pub struct VirtualButtons<D>
+where
+    D: VirpilDeviceDescription,
+{
+    buttons: Vec<(VirtualButton<D::Axis>, bool)>,
+}
+impl<D> Default for VirtualButtons<D>
+where
+    D: VirpilDeviceDescription,
+{
+    fn default() -> Self {
+        Self {
+            buttons: Vec::new(),
+        }
+    }
+}
+impl<D> VirtualButtons<D>
+where
+    D: VirpilDeviceDescription,
+{
+    /// Adds `button`, replacing any button with the same name, and returns the old one with whether it was pressed.
+    /// A new button starts released.
+    pub fn insert(
+        &mut self,
+        button: VirtualButton<D::Axis>,
+    ) -> Option<(VirtualButton<D::Axis>, bool)> {
+        match self
+            .buttons
+            .iter_mut()
+            .find(|(existing, _)| existing.name == button.name)
+        {
+            Some(existing) => Some(std::mem::replace(existing, (button, false))),
+            None => {
+                self.buttons.push((button, false));
+                None
+            }
+        }
+    }
+
+    /// Returns the removed button and whether it was pressed.
+    pub fn remove(&mut self, name: &str) -> Option<(VirtualButton<D::Axis>, bool)> {
+        let index = self
+            .buttons
+            .iter()
+            .position(|(button, _)| button.name == name)?;
+        Some(self.buttons.remove(index))
+    }
+
+    /// `None` if no button with that name is defined.
+    pub fn state(&self, name: &str) -> Option<bool> {
+        self.buttons
+            .iter()
+            .find(|(button, _)| button.name == name)
+            .map(|(_, pressed)| *pressed)
+    }
+
+    /// Names and states in definition order.
+    pub fn states(&self) -> Vec<(&'static str, bool)> {
+        self.buttons
+            .iter()
+            .map(|(button, pressed)| (button.name, *pressed))
+            .collect()
+    }
+
+    /// Re-evaluates every button against the pipeline's latest outputs, pushing a press or release for each change.
+    pub fn update(&mut self, pipeline: &AxisPipeline<D>, events: &mut Vec<DeviceEventKind<D>>) {
+        for (button, pressed) in self.buttons.iter_mut() {
+            let next = button.next_state(*pressed, pipeline.output(button.axis));
+            if next != *pressed {
+                *pressed = next;
+                events.push(if next {
+                    InputEventKind::VirtualButtonPressed(button.name)
+                } else {
+                    InputEventKind::VirtualButtonReleased(button.name)
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use strum::EnumCount;
+
+    use super::*;
+    use crate::axis::{AxisConfig, Calibration};
+    use crate::simulator::{wait_until, SimulatedBackend};
+    use crate::throttle::{Throttle, ThrottleAxis};
+    use crate::virpil_device::{find_device, ToAxisIndex};
+    use crate::LedPower;
+
+    /// A pipeline where raw `0..=1000` on the slider is the processed value times 1000.
+    fn pipeline(slider: u16) -> AxisPipeline<Throttle> {
+        let mut pipeline = AxisPipeline::default();
+        pipeline.set_config(
+            ThrottleAxis::Slider,
+            AxisConfig {
+                calibration: Calibration::NoCenter { min: 0, max: 1000 },
+                ..AxisConfig::default()
+            },
+        );
+        let mut raw = [0; ThrottleAxis::COUNT];
+        raw[ThrottleAxis::Slider.to_axis_index() as usize] = slider;
+        pipeline.update(&raw);
+        pipeline
+    }
+
+    fn update(
+        buttons: &mut VirtualButtons<Throttle>,
+        slider: u16,
+    ) -> Vec<DeviceEventKind<Throttle>> {
+        let mut events = Vec::new();
+        buttons.update(&pipeline(slider), &mut events);
+        events
+    }
+
+    #[test]
+    fn hysteresis_widens_the_range_once_pressed() {
+        let mut buttons = VirtualButtons::<Throttle>::default();
+        buttons.insert(
+            VirtualButton::new("detent", ThrottleAxis::Slider, 0.8, 0.6).with_hysteresis(0.05),
+        );
+        let mut was_pressed = false;
+        for (slider, pressed) in [
+            (580, false),
+            (600, true),
+            (820, true),
+            (850, true),
+            (860, false),
+            (820, false),
+            (700, true),
+            (560, true),
+            (540, false),
+        ] {
+            let expected = match (was_pressed, pressed) {
+                (false, true) => vec![InputEventKind::VirtualButtonPressed("detent")],
+                (true, false) => vec![InputEventKind::VirtualButtonReleased("detent")],
+                _ => Vec::new(),
+            };
+            assert_eq!(update(&mut buttons, slider), expected, "at {}", slider);
+            assert_eq!(buttons.state("detent"), Some(pressed), "at {}", slider);
+            was_pressed = pressed;
+        }
+    }
+
+    #[test]
+    fn replacing_a_button_starts_it_released() {
+        let mut buttons = VirtualButtons::<Throttle>::default();
+        buttons.insert(VirtualButton::new("idle", ThrottleAxis::Slider, 0.0, 0.1));
+        update(&mut buttons, 0);
+        let (old, pressed) = buttons
+            .insert(VirtualButton::new("idle", ThrottleAxis::Slider, 0.0, 0.2))
+            .unwrap();
+        assert_eq!(old.end, 0.1);
+        assert!(pressed);
+        assert_eq!(buttons.state("idle"), Some(false));
+        assert_eq!(buttons.states(), vec![("idle", false)]);
+        assert_eq!(
+            buttons.remove("idle").map(|(_, pressed)| pressed),
+            Some(false)
+        );
+        assert_eq!(buttons.state("idle"), None);
+    }
+
+    #[test]
+    fn device_releases_replaced_buttons_and_snapshots_them() {
+        let backend = SimulatedBackend::new();
+        let mut simulated = backend.add_device::<Throttle>();
+        let device = find_device::<Throttle, _>(&backend, LedPower::OFF).unwrap();
+        device.add_virtual_button(VirtualButton::new("idle", ThrottleAxis::Slider, 0.0, 0.1));
+        let events = device.subscribe();
+        simulated.set_axis(ThrottleAxis::Slider, 0).send_report();
+        assert!(wait_until(
+            || device.snapshot().virtual_button("idle") == Some(true)
+        ));
+        assert_eq!(device.virtual_button_state("idle"), Some(true));
+        device.add_virtual_button(VirtualButton::new("idle", ThrottleAxis::Slider, 0.5, 1.0));
+        assert_eq!(device.snapshot().virtual_button("idle"), Some(false));
+        let kinds: Vec<_> = events
+            .recv_timeout(Duration::from_secs(1))
+            .into_iter()
+            .chain(events.recv_timeout(Duration::from_secs(1)))
+            .map(|event| event.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                InputEventKind::VirtualButtonPressed("idle"),
+                InputEventKind::VirtualButtonReleased("idle"),
+            ]
+        );
+        device.remove_virtual_button("idle");
+        assert_eq!(device.snapshot().virtual_button("idle"), None);
+    }
+
+    #[test]
+    fn new_buttons_are_in_the_snapshot_right_away() {
+        let backend = SimulatedBackend::new();
+        backend.add_device::<Throttle>();
+        let device = find_device::<Throttle, _>(&backend, LedPower::OFF).unwrap();
+        let events = device.subscribe();
+        assert!(device
+            .add_virtual_button(VirtualButton::new("idle", ThrottleAxis::Slider, 0.0, 0.1))
+            .is_none());
+        assert_eq!(device.snapshot().virtual_button("idle"), Some(false));
+        assert_eq!(device.virtual_button_state("idle"), Some(false));
+        assert!(events.try_recv().is_err());
+        assert!(device.remove_virtual_button("missing").is_none());
+        assert_eq!(device.snapshot().virtual_buttons, vec![("idle", false)]);
+    }
+}